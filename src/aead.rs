@@ -1,8 +1,11 @@
+use std::fmt;
+use std::marker::PhantomData;
 use std::mem;
 use std::os::raw::*;
 use std::ptr;
 
-use {ffi, Result};
+use {ffi, util, Result};
+use error::Error;
 use symmetric::Cipher;
 
 /// Authenticated encryption mode.
@@ -65,6 +68,9 @@ impl EaxState {
     }
 
     /// Decrypts the given data in place.
+    ///
+    /// The plaintext is not authenticated until the tag from [`finish`](#method.finish) was compared, use
+    /// [`open`](#method.open) to decrypt and verify a message in one step.
     pub fn decrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
         unsafe {
             tryt!(ffi::eax_decrypt(
@@ -78,6 +84,9 @@ impl EaxState {
     }
 
     /// Decrypts the given data.
+    ///
+    /// The plaintext is not authenticated until the tag from [`finish`](#method.finish) was compared, use
+    /// [`open`](#method.open) to decrypt and verify a message in one step.
     pub fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
         unsafe {
             let mut res = vec![0; data.len()];
@@ -101,33 +110,1992 @@ impl EaxState {
         }
         Ok(res)
     }
+
+    /// Encrypt a message in one step and return the ciphertext and an authentication tag of up to `tag_len` bytes.
+    ///
+    /// The tag is at most as long as the block size of the cipher.
+    pub fn seal(cipher: Cipher, key: &[u8], nonce: &[u8], header: Option<&[u8]>, plaintext: &[u8], tag_len: usize)
+        -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut ciphertext = vec![0; plaintext.len()];
+        let mut tag = vec![0; tag_len];
+        let mut len = tag_len as c_ulong;
+        let (h, h_len) = slice_ptr(header);
+
+        unsafe {
+            tryt!(ffi::eax_encrypt_authenticate_memory(
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                nonce.as_ptr(),
+                nonce.len() as c_ulong,
+                h,
+                h_len,
+                plaintext.as_ptr(),
+                plaintext.len() as c_ulong,
+                ciphertext.as_mut_ptr(),
+                tag.as_mut_ptr(),
+                &mut len
+            ));
+        }
+
+        tag.truncate(len as usize);
+        Ok((ciphertext, tag))
+    }
+
+    /// Decrypt a message in one step and verify its authentication tag.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn open(cipher: Cipher, key: &[u8], nonce: &[u8], header: Option<&[u8]>, ciphertext: &[u8], tag: &[u8])
+        -> Result<Vec<u8>> {
+        // An empty tag would always match
+        if tag.is_empty() || tag.len() > cipher.block_size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let mut plaintext = vec![0; ciphertext.len()];
+        let mut stat = 0;
+        let (h, h_len) = slice_ptr(header);
+
+        unsafe {
+            tryt!(ffi::eax_decrypt_verify_memory(
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                nonce.as_ptr(),
+                nonce.len() as c_ulong,
+                h,
+                h_len,
+                ciphertext.as_ptr(),
+                ciphertext.len() as c_ulong,
+                plaintext.as_mut_ptr(),
+                tag.as_ptr() as *mut _,
+                tag.len() as c_ulong,
+                &mut stat
+            ));
+        }
+
+        if stat != 1 {
+            util::zeromem_slice(&mut plaintext);
+            return Err(Error::InvalidTag);
+        }
+        Ok(plaintext)
+    }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
+/// Galois/Counter Mode, authenticated encryption for ciphers with a block size of 16 bytes.
+///
+/// The additional authenticated data has to be added with [`add_aad`] before any data is encrypted or decrypted.
+/// After [`finish`], the state can be used for another message with [`reset`], which keeps the key schedule and the
+/// precomputed GHASH tables.
+///
+/// [`add_aad`]: #method.add_aad
+/// [`finish`]: #method.finish
+/// [`reset`]: #method.reset
+#[derive(Clone)]
+pub struct Gcm {
+    // The state contains 64 KiB of multiplication tables, so keep it off the stack.
+    state: Box<ffi::gcm_state>,
+}
 
-    #[test]
-    fn test_eax_loop() {
-        let key = [1; 16];
-        let nonce = [2; 16];
-        let header = [3; 3];
-        let data = [4; 10];
-        let tag_len = 7;
+impl Gcm {
+    /// Create a new gcm mode state from a key and an initialization vector.
+    ///
+    /// The IV must never be reused with the same key. A length of 12 bytes is recommended.
+    pub fn new(cipher: Cipher, key: &[u8], iv: &[u8]) -> Result<Self> {
+        let mut gcm = unsafe {
+            let mut state: Box<ffi::gcm_state> = Box::new(mem::zeroed());
+            tryt!(ffi::gcm_init(&mut *state, cipher.index(), key.as_ptr(), key.len() as c_int));
+            Gcm { state }
+        };
 
-        // Encrypt
-        let mut eax = EaxState::new(Cipher::aes(), &key, &nonce, Some(&header))
-            .unwrap();
-        let enc = eax.encrypt(&data).unwrap();
-        let tag = eax.finish(tag_len).unwrap();
+        gcm.add_iv(iv)?;
+        Ok(gcm)
+    }
 
-        // Decrypt
-        let mut eax = EaxState::new(Cipher::aes(), &key, &nonce, Some(&header))
-            .unwrap();
-        let dec = eax.decrypt(&enc).unwrap();
-        let tag2 = eax.finish(tag_len).unwrap();
+    /// Add (public) data that influences the authentication tag but is not encrypted.
+    ///
+    /// This can be called multiple times, but only before the first call to encrypt or decrypt.
+    pub fn add_aad(&mut self, aad: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::gcm_add_aad(&mut *self.state, aad.as_ptr(), aad.len() as c_ulong));
+        }
+        Ok(())
+    }
 
-        assert_eq!(tag, tag2);
-        assert_eq!(&data, dec.as_slice());
+    /// Encrypts the given data in place.
+    pub fn encrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::gcm_process(
+                &mut *self.state,
+                data.as_mut_ptr(),
+                data.len() as c_ulong,
+                data.as_mut_ptr(),
+                ffi::GCM_ENCRYPT as c_int
+            ));
+        }
+        Ok(())
+    }
+
+    /// Encrypts the given data.
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.encrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Decrypts the given data in place.
+    ///
+    /// The plaintext is not authenticated until the tag from [`finish`](#method.finish) was compared.
+    pub fn decrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::gcm_process(
+                &mut *self.state,
+                data.as_mut_ptr(),
+                data.len() as c_ulong,
+                data.as_mut_ptr(),
+                ffi::GCM_DECRYPT as c_int
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decrypts the given data.
+    ///
+    /// The plaintext is not authenticated until the tag from [`finish`](#method.finish) was compared.
+    pub fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.decrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Generate the authentication tag with the given length of at most 16 bytes.
+    ///
+    /// Use [`reset`](#method.reset) to process another message afterwards.
+    pub fn finish(&mut self, tag_len: usize) -> Result<Vec<u8>> {
+        let mut res = vec![0; tag_len];
+        unsafe {
+            let mut len = tag_len as c_ulong;
+            tryt!(ffi::gcm_done(&mut *self.state, res.as_mut_ptr(), &mut len));
+            res.truncate(len as usize);
+        }
+        Ok(res)
+    }
+
+    /// Start a new message with the same key and a new initialization vector.
+    pub fn reset(&mut self, iv: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::gcm_reset(&mut *self.state));
+        }
+        self.add_iv(iv)
+    }
+
+    /// Encrypt a message in one step and return the ciphertext and an authentication tag of `tag_len` bytes.
+    pub fn seal(cipher: Cipher, key: &[u8], iv: &[u8], aad: Option<&[u8]>, plaintext: &[u8], tag_len: usize)
+        -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut ciphertext = vec![0; plaintext.len()];
+        let mut tag = vec![0; tag_len];
+        let mut len = tag_len as c_ulong;
+        let (a, a_len) = slice_ptr(aad);
+
+        unsafe {
+            tryt!(ffi::gcm_memory(
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                iv.as_ptr(),
+                iv.len() as c_ulong,
+                a,
+                a_len,
+                plaintext.as_ptr() as *mut _,
+                plaintext.len() as c_ulong,
+                ciphertext.as_mut_ptr(),
+                tag.as_mut_ptr(),
+                &mut len,
+                ffi::GCM_ENCRYPT as c_int
+            ));
+        }
+
+        tag.truncate(len as usize);
+        Ok((ciphertext, tag))
+    }
+
+    /// Decrypt a message in one step and verify its authentication tag.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned. Tags shorter
+    /// than 4 bytes are rejected.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn open(cipher: Cipher, key: &[u8], iv: &[u8], aad: Option<&[u8]>, ciphertext: &[u8], tag: &[u8])
+        -> Result<Vec<u8>> {
+        if tag.len() < 4 || tag.len() > 16 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let mut plaintext = vec![0; ciphertext.len()];
+        let mut computed = [0; 16];
+        let mut len = tag.len() as c_ulong;
+        let (a, a_len) = slice_ptr(aad);
+
+        unsafe {
+            tryt!(ffi::gcm_memory(
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                iv.as_ptr(),
+                iv.len() as c_ulong,
+                a,
+                a_len,
+                plaintext.as_mut_ptr(),
+                plaintext.len() as c_ulong,
+                ciphertext.as_ptr() as *mut _,
+                computed.as_mut_ptr(),
+                &mut len,
+                ffi::GCM_DECRYPT as c_int
+            ));
+        }
+
+        if !util::compare_slices(&computed[..len as usize], tag) {
+            util::zeromem_slice(&mut plaintext);
+            return Err(Error::InvalidTag);
+        }
+        Ok(plaintext)
+    }
+
+    fn add_iv(&mut self, iv: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::gcm_add_iv(&mut *self.state, iv.as_ptr(), iv.len() as c_ulong));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Gcm {
+    fn drop(&mut self) {
+        unsafe {
+            util::zeromem(&mut *self.state);
+        }
+    }
+}
+
+/// Type state of a [`Ccm`](struct.Ccm.html) that expects the additional authenticated data.
+pub enum CcmAad {}
+
+/// Type state of a [`Ccm`](struct.Ccm.html) that encrypts or decrypts the payload.
+pub enum CcmPayload {}
+
+/// Counter with CBC-MAC mode, authenticated encryption for ciphers with a block size of 16 bytes.
+///
+/// CCM has to know the length of the additional authenticated data and of the payload before it starts, so they are
+/// declared in [`new`]. The state then goes through two stages: First, exactly the declared amount of additional
+/// authenticated data is added with [`add_aad`], then [`start`] switches to the payload. The payload is encrypted or
+/// decrypted, again exactly the declared amount, and [`finish`] returns the authentication tag. Adding more or less
+/// data than declared fails with [`Error::LengthMismatch`]. If [`start`] or [`finish`] fail, the state is returned
+/// together with the error, so the missing data can still be added.
+///
+/// [`new`]: #method.new
+/// [`add_aad`]: #method.add_aad
+/// [`start`]: #method.start
+/// [`finish`]: #method.finish
+/// [`Error::LengthMismatch`]: ../enum.Error.html#variant.LengthMismatch
+pub struct Ccm<S = CcmAad> {
+    // Boxed so that a state returned together with an error stays small.
+    state: Box<ffi::ccm_state>,
+    _state: PhantomData<S>,
+}
+
+impl<S> Clone for Ccm<S> {
+    fn clone(&self) -> Self {
+        Ccm { state: self.state.clone(), _state: PhantomData }
+    }
+}
+
+impl Ccm<CcmAad> {
+    /// Create a new ccm mode state from a key and a nonce.
+    ///
+    /// The nonce must be between 7 and 13 bytes long, and a shorter nonce allows a longer payload. `aad_len` and
+    /// `payload_len` are the exact lengths of the additional authenticated data and the payload. The tag length has
+    /// to be even and between 4 and 16 bytes.
+    pub fn new(cipher: Cipher, key: &[u8], nonce: &[u8], aad_len: usize, payload_len: usize, tag_len: usize)
+        -> Result<Self> {
+        ccm_check(nonce.len(), aad_len, payload_len, tag_len)?;
+
+        unsafe {
+            let mut state: Box<ffi::ccm_state> = Box::new(mem::zeroed());
+            tryt!(ffi::ccm_init(
+                &mut *state,
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_int,
+                payload_len as c_int,
+                tag_len as c_int,
+                aad_len as c_int
+            ));
+            tryt!(ffi::ccm_add_nonce(&mut *state, nonce.as_ptr(), nonce.len() as c_ulong));
+
+            Ok(Ccm { state, _state: PhantomData })
+        }
+    }
+
+    /// Add (public) data that influences the authentication tag but is not encrypted.
+    ///
+    /// This can be called multiple times until the declared length is reached.
+    pub fn add_aad(&mut self, aad: &[u8]) -> Result<()> {
+        let expected = self.state.aadlen as usize;
+        let actual = self.state.current_aadlen as usize + aad.len();
+        if actual > expected {
+            return Err(Error::LengthMismatch { expected, actual });
+        }
+
+        unsafe {
+            tryt!(ffi::ccm_add_aad(&mut *self.state, aad.as_ptr(), aad.len() as c_ulong));
+        }
+        Ok(())
+    }
+
+    /// Finish the additional authenticated data and continue with the payload.
+    pub fn start(self) -> ::std::result::Result<Ccm<CcmPayload>, (Self, Error)> {
+        let expected = self.state.aadlen as usize;
+        let actual = self.state.current_aadlen as usize;
+        if actual != expected {
+            return Err((self, Error::LengthMismatch { expected, actual }));
+        }
+
+        // Move the state out without zeroing it in drop.
+        let ccm = mem::ManuallyDrop::new(self);
+        Ok(Ccm { state: unsafe { ptr::read(&ccm.state) }, _state: PhantomData })
+    }
+
+    /// Encrypt a message in one step and return the ciphertext and an authentication tag of `tag_len` bytes.
+    pub fn seal(cipher: Cipher, key: &[u8], nonce: &[u8], aad: Option<&[u8]>, plaintext: &[u8], tag_len: usize)
+        -> Result<(Vec<u8>, Vec<u8>)> {
+        let (a, a_len) = slice_ptr(aad);
+        ccm_check(nonce.len(), a_len as usize, plaintext.len(), tag_len)?;
+
+        let mut ciphertext = vec![0; plaintext.len()];
+        let mut tag = vec![0; tag_len];
+        let mut len = tag_len as c_ulong;
+
+        unsafe {
+            tryt!(ffi::ccm_memory(
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                ptr::null_mut(),
+                nonce.as_ptr(),
+                nonce.len() as c_ulong,
+                a,
+                a_len,
+                plaintext.as_ptr() as *mut _,
+                plaintext.len() as c_ulong,
+                ciphertext.as_mut_ptr(),
+                tag.as_mut_ptr(),
+                &mut len,
+                ffi::CCM_ENCRYPT as c_int
+            ));
+        }
+
+        Ok((ciphertext, tag))
+    }
+
+    /// Decrypt a message in one step and verify its authentication tag.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn open(cipher: Cipher, key: &[u8], nonce: &[u8], aad: Option<&[u8]>, ciphertext: &[u8], tag: &[u8])
+        -> Result<Vec<u8>> {
+        let (a, a_len) = slice_ptr(aad);
+        ccm_check(nonce.len(), a_len as usize, ciphertext.len(), tag.len())?;
+
+        let mut plaintext = vec![0; ciphertext.len()];
+        let mut len = tag.len() as c_ulong;
+
+        unsafe {
+            let code = ffi::ccm_memory(
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                ptr::null_mut(),
+                nonce.as_ptr(),
+                nonce.len() as c_ulong,
+                a,
+                a_len,
+                plaintext.as_mut_ptr(),
+                plaintext.len() as c_ulong,
+                ciphertext.as_ptr() as *mut _,
+                tag.as_ptr() as *mut _,
+                &mut len,
+                ffi::CCM_DECRYPT as c_int
+            );
+
+            // The plaintext was already zeroed if the tag does not match
+            match mem::transmute::<c_int, ffi::Error>(code) {
+                ffi::CRYPT_OK => Ok(plaintext),
+                ffi::CRYPT_ERROR => Err(Error::InvalidTag),
+                e => Err(Error::from_code(e)),
+            }
+        }
+    }
+}
+
+impl Ccm<CcmPayload> {
+    /// Encrypts the given data in place.
+    pub fn encrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        self.check_payload_len(data.len())?;
+        unsafe {
+            tryt!(ffi::ccm_process(
+                &mut *self.state,
+                data.as_mut_ptr(),
+                data.len() as c_ulong,
+                data.as_mut_ptr(),
+                ffi::CCM_ENCRYPT as c_int
+            ));
+        }
+        Ok(())
+    }
+
+    /// Encrypts the given data.
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.encrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Decrypts the given data in place.
+    ///
+    /// The plaintext is not authenticated until the tag from [`finish`](#method.finish) was compared.
+    pub fn decrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        self.check_payload_len(data.len())?;
+        unsafe {
+            tryt!(ffi::ccm_process(
+                &mut *self.state,
+                data.as_mut_ptr(),
+                data.len() as c_ulong,
+                data.as_mut_ptr(),
+                ffi::CCM_DECRYPT as c_int
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decrypts the given data.
+    ///
+    /// The plaintext is not authenticated until the tag from [`finish`](#method.finish) was compared.
+    pub fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.decrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Generate the authentication tag with the length that was given to [`new`](#method.new).
+    pub fn finish(mut self) -> ::std::result::Result<Vec<u8>, (Self, Error)> {
+        let expected = self.state.ptlen as usize;
+        let actual = self.state.current_ptlen as usize;
+        if actual != expected {
+            return Err((self, Error::LengthMismatch { expected, actual }));
+        }
+
+        let mut res = vec![0; self.state.taglen as usize];
+        let mut len = res.len() as c_ulong;
+        let code = unsafe { ffi::ccm_done(&mut *self.state, res.as_mut_ptr(), &mut len) };
+        match unsafe { mem::transmute::<c_int, ffi::Error>(code) } {
+            ffi::CRYPT_OK => {
+                res.truncate(len as usize);
+                Ok(res)
+            }
+            e => Err((self, Error::from_code(e))),
+        }
+    }
+
+    fn check_payload_len(&self, len: usize) -> Result<()> {
+        let expected = self.state.ptlen as usize;
+        let actual = self.state.current_ptlen as usize + len;
+        if actual > expected {
+            return Err(Error::LengthMismatch { expected, actual });
+        }
+        Ok(())
+    }
+}
+
+impl<S> fmt::Debug for Ccm<S> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Ccm")
+            .field("aad_len", &self.state.aadlen)
+            .field("current_aad_len", &self.state.current_aadlen)
+            .field("payload_len", &self.state.ptlen)
+            .field("current_payload_len", &self.state.current_ptlen)
+            .finish()
+    }
+}
+
+impl<S> Drop for Ccm<S> {
+    fn drop(&mut self) {
+        unsafe {
+            util::zeromem(&mut *self.state);
+        }
+    }
+}
+
+/// Check the parameters of CCM, LibTomCrypt silently adjusts invalid lengths.
+fn ccm_check(nonce_len: usize, aad_len: usize, payload_len: usize, tag_len: usize) -> Result<()> {
+    if !(7..=13).contains(&nonce_len) || !(4..=16).contains(&tag_len) || tag_len % 2 != 0 {
+        return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+    }
+
+    // The payload length is stored in the bytes of the first block that are not used by the nonce
+    let max_payload_bits = 8 * (15 - nonce_len);
+    if aad_len > c_int::MAX as usize || payload_len > c_int::MAX as usize
+        || (max_payload_bits < 32 && payload_len >> max_payload_bits != 0) {
+        return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+    }
+    Ok(())
+}
+
+/// Offset Codebook mode version 3 (RFC 7253), authenticated encryption for ciphers with a block size of 16 bytes.
+///
+/// Data is encrypted or decrypted in multiples of the block size with [`encrypt`] and [`decrypt`], the remaining data
+/// of any length is passed to [`encrypt_last`] or [`decrypt_last`], which also finish the message. Additional
+/// authenticated data can be added with [`add_aad`] at any time before that.
+///
+/// [`encrypt`]: #method.encrypt
+/// [`decrypt`]: #method.decrypt
+/// [`encrypt_last`]: #method.encrypt_last
+/// [`decrypt_last`]: #method.decrypt_last
+/// [`add_aad`]: #method.add_aad
+#[derive(Clone)]
+pub struct Ocb3(ffi::ocb3_state);
+
+impl Ocb3 {
+    /// Create a new ocb3 mode state from a key and a nonce.
+    ///
+    /// The nonce can be up to 15 bytes long and must never be reused with the same key, 12 bytes are recommended.
+    /// The tag length has to be between 1 and 16 bytes.
+    pub fn new(cipher: Cipher, key: &[u8], nonce: &[u8], tag_len: usize) -> Result<Self> {
+        if tag_len == 0 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        unsafe {
+            let mut state = mem::zeroed();
+            tryt!(ffi::ocb3_init(
+                &mut state,
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                nonce.as_ptr(),
+                nonce.len() as c_ulong,
+                tag_len as c_ulong
+            ));
+            Ok(Ocb3(state))
+        }
+    }
+
+    /// Add (public) data that influences the authentication tag but is not encrypted.
+    pub fn add_aad(&mut self, aad: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::ocb3_add_aad(&mut self.0, aad.as_ptr(), aad.len() as c_ulong));
+        }
+        Ok(())
+    }
+
+    /// Encrypts the given data in place, the length has to be a multiple of the block size.
+    pub fn encrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        // The checksum is computed from the input after the output was written
+        let mut input = data.to_vec();
+        let res = self.encrypt_into(&input, data);
+        util::zeromem_slice(&mut input);
+        res
+    }
+
+    /// Encrypts the given data, the length has to be a multiple of the block size.
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = vec![0; data.len()];
+        self.encrypt_into(data, &mut res)?;
+        Ok(res)
+    }
+
+    fn encrypt_into(&mut self, input: &[u8], output: &mut [u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::ocb3_encrypt(&mut self.0, input.as_ptr(), input.len() as c_ulong, output.as_mut_ptr()));
+        }
+        Ok(())
+    }
+
+    /// Decrypts the given data in place, the length has to be a multiple of the block size.
+    ///
+    /// The plaintext is not authenticated until [`decrypt_last`](#method.decrypt_last) succeeded.
+    pub fn decrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::ocb3_decrypt(&mut self.0, data.as_ptr(), data.len() as c_ulong, data.as_mut_ptr()));
+        }
+        Ok(())
+    }
+
+    /// Decrypts the given data, the length has to be a multiple of the block size.
+    ///
+    /// The plaintext is not authenticated until [`decrypt_last`](#method.decrypt_last) succeeded.
+    pub fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.decrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Encrypt the remaining data of any length in place and return the authentication tag.
+    pub fn encrypt_last_in_place(mut self, data: &mut [u8]) -> Result<Vec<u8>> {
+        let mut input = data.to_vec();
+        let res = self.encrypt_last_into(&input, data);
+        util::zeromem_slice(&mut input);
+        res?;
+        self.done()
+    }
+
+    /// Encrypt the remaining data of any length and return it together with the authentication tag.
+    pub fn encrypt_last(mut self, data: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut res = vec![0; data.len()];
+        self.encrypt_last_into(data, &mut res)?;
+        Ok((res, self.done()?))
+    }
+
+    fn encrypt_last_into(&mut self, input: &[u8], output: &mut [u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::ocb3_encrypt_last(&mut self.0, input.as_ptr(), input.len() as c_ulong, output.as_mut_ptr()));
+        }
+        Ok(())
+    }
+
+    /// Decrypt the remaining data of any length in place and verify the authentication tag.
+    ///
+    /// If the tag does not match, the data is zeroed and [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn decrypt_last_in_place(mut self, data: &mut [u8], tag: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::ocb3_decrypt_last(&mut self.0, data.as_ptr(), data.len() as c_ulong, data.as_mut_ptr()));
+        }
+
+        if !util::compare_slices(&self.done()?, tag) {
+            util::zeromem_slice(data);
+            return Err(Error::InvalidTag);
+        }
+        Ok(())
+    }
+
+    /// Decrypt the remaining data of any length and verify the authentication tag.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn decrypt_last(self, data: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.decrypt_last_in_place(&mut res, tag)?;
+        Ok(res)
+    }
+
+    /// Encrypt a message in one step and return the ciphertext and an authentication tag of `tag_len` bytes.
+    pub fn seal(cipher: Cipher, key: &[u8], nonce: &[u8], aad: Option<&[u8]>, plaintext: &[u8], tag_len: usize)
+        -> Result<(Vec<u8>, Vec<u8>)> {
+        if tag_len == 0 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let mut ciphertext = vec![0; plaintext.len()];
+        let mut tag = vec![0; tag_len];
+        let mut len = tag_len as c_ulong;
+        let (a, a_len) = slice_ptr(aad);
+
+        unsafe {
+            tryt!(ffi::ocb3_encrypt_authenticate_memory(
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                nonce.as_ptr(),
+                nonce.len() as c_ulong,
+                a,
+                a_len,
+                plaintext.as_ptr(),
+                plaintext.len() as c_ulong,
+                ciphertext.as_mut_ptr(),
+                tag.as_mut_ptr(),
+                &mut len
+            ));
+        }
+
+        tag.truncate(len as usize);
+        Ok((ciphertext, tag))
+    }
+
+    /// Decrypt a message in one step and verify its authentication tag.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn open(cipher: Cipher, key: &[u8], nonce: &[u8], aad: Option<&[u8]>, ciphertext: &[u8], tag: &[u8])
+        -> Result<Vec<u8>> {
+        // An empty tag would always match
+        if tag.is_empty() || tag.len() > 16 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let mut plaintext = vec![0; ciphertext.len()];
+        let mut stat = 0;
+        let (a, a_len) = slice_ptr(aad);
+
+        unsafe {
+            tryt!(ffi::ocb3_decrypt_verify_memory(
+                cipher.index(),
+                key.as_ptr(),
+                key.len() as c_ulong,
+                nonce.as_ptr(),
+                nonce.len() as c_ulong,
+                a,
+                a_len,
+                ciphertext.as_ptr(),
+                ciphertext.len() as c_ulong,
+                plaintext.as_mut_ptr(),
+                tag.as_ptr(),
+                tag.len() as c_ulong,
+                &mut stat
+            ));
+        }
+
+        if stat != 1 {
+            util::zeromem_slice(&mut plaintext);
+            return Err(Error::InvalidTag);
+        }
+        Ok(plaintext)
+    }
+
+    fn done(&mut self) -> Result<Vec<u8>> {
+        let mut res = vec![0; self.0.tag_len as usize];
+        unsafe {
+            let mut len = res.len() as c_ulong;
+            tryt!(ffi::ocb3_done(&mut self.0, res.as_mut_ptr(), &mut len));
+            res.truncate(len as usize);
+        }
+        Ok(res)
+    }
+}
+
+impl Drop for Ocb3 {
+    fn drop(&mut self) {
+        unsafe {
+            util::zeromem(&mut self.0);
+        }
+    }
+}
+
+/// The original Offset Codebook mode, authenticated encryption without additional authenticated data.
+///
+/// Only use this to interoperate with existing data, new protocols should use [`Ocb3`](struct.Ocb3.html). Whole
+/// blocks can be processed with [`encrypt`] and [`decrypt`], but the final block, even if it is complete, has to be
+/// passed to [`encrypt_last`] or [`decrypt_last`].
+///
+/// [`encrypt`]: #method.encrypt
+/// [`decrypt`]: #method.decrypt
+/// [`encrypt_last`]: #method.encrypt_last
+/// [`decrypt_last`]: #method.decrypt_last
+#[derive(Clone)]
+pub struct Ocb(ffi::ocb_state);
+
+impl Ocb {
+    /// Create a new ocb mode state from a key and a nonce, which has to be as long as the block size.
+    pub fn new(cipher: Cipher, key: &[u8], nonce: &[u8]) -> Result<Self> {
+        if nonce.len() != cipher.block_size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        unsafe {
+            let mut state = mem::zeroed();
+            tryt!(ffi::ocb_init(&mut state, cipher.index(), key.as_ptr(), key.len() as c_ulong, nonce.as_ptr()));
+            Ok(Ocb(state))
+        }
+    }
+
+    /// Encrypts the given data in place, the length has to be a multiple of the block size.
+    pub fn encrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        let block_len = self.block_len();
+        if data.len() % block_len != 0 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        for block in data.chunks_mut(block_len) {
+            unsafe {
+                tryt!(ffi::ocb_encrypt(&mut self.0, block.as_ptr(), block.as_mut_ptr()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Encrypts the given data, the length has to be a multiple of the block size.
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.encrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Decrypts the given data in place, the length has to be a multiple of the block size.
+    ///
+    /// The plaintext is not authenticated until [`decrypt_last`](#method.decrypt_last) succeeded.
+    pub fn decrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        let block_len = self.block_len();
+        if data.len() % block_len != 0 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        for block in data.chunks_mut(block_len) {
+            unsafe {
+                tryt!(ffi::ocb_decrypt(&mut self.0, block.as_ptr(), block.as_mut_ptr()));
+            }
+        }
+        Ok(())
+    }
+
+    /// Decrypts the given data, the length has to be a multiple of the block size.
+    ///
+    /// The plaintext is not authenticated until [`decrypt_last`](#method.decrypt_last) succeeded.
+    pub fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.decrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Encrypt the remaining data of any length in place and return an authentication tag of up to `tag_len` bytes.
+    ///
+    /// The tag is at most as long as the block size.
+    pub fn encrypt_last_in_place(mut self, data: &mut [u8], tag_len: usize) -> Result<Vec<u8>> {
+        let split = self.last_block_start(data.len());
+        let (blocks, last) = data.split_at_mut(split);
+        self.encrypt_in_place(blocks)?;
+
+        let mut tag = vec![0; tag_len];
+        unsafe {
+            let mut len = tag_len as c_ulong;
+            tryt!(ffi::ocb_done_encrypt(
+                &mut self.0,
+                last.as_ptr(),
+                last.len() as c_ulong,
+                last.as_mut_ptr(),
+                tag.as_mut_ptr(),
+                &mut len
+            ));
+            tag.truncate(len as usize);
+        }
+        Ok(tag)
+    }
+
+    /// Encrypt the remaining data of any length and return it together with an authentication tag of up to `tag_len`
+    /// bytes.
+    pub fn encrypt_last(self, data: &[u8], tag_len: usize) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut res = data.to_vec();
+        let tag = self.encrypt_last_in_place(&mut res, tag_len)?;
+        Ok((res, tag))
+    }
+
+    /// Decrypt the remaining data of any length in place and verify the authentication tag.
+    ///
+    /// If the tag does not match, the data is zeroed and [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn decrypt_last_in_place(mut self, data: &mut [u8], tag: &[u8]) -> Result<()> {
+        // An empty tag would always match
+        if tag.is_empty() || tag.len() > self.block_len() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let split = self.last_block_start(data.len());
+        self.decrypt_in_place(&mut data[..split])?;
+
+        let mut stat = 0;
+        {
+            // LibTomCrypt reads the ciphertext of the last block from the output buffer, so it has to be in place.
+            let last = &mut data[split..];
+            unsafe {
+                tryt!(ffi::ocb_done_decrypt(
+                    &mut self.0,
+                    last.as_ptr(),
+                    last.len() as c_ulong,
+                    last.as_mut_ptr(),
+                    tag.as_ptr(),
+                    tag.len() as c_ulong,
+                    &mut stat
+                ));
+            }
+        }
+
+        if stat != 1 {
+            util::zeromem_slice(data);
+            return Err(Error::InvalidTag);
+        }
+        Ok(())
+    }
+
+    /// Decrypt the remaining data of any length and verify the authentication tag.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn decrypt_last(self, data: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.decrypt_last_in_place(&mut res, tag)?;
+        Ok(res)
+    }
+
+    /// Encrypt a message in one step and return the ciphertext and an authentication tag of up to `tag_len` bytes.
+    pub fn seal(cipher: Cipher, key: &[u8], nonce: &[u8], plaintext: &[u8], tag_len: usize)
+        -> Result<(Vec<u8>, Vec<u8>)> {
+        Self::new(cipher, key, nonce)?.encrypt_last(plaintext, tag_len)
+    }
+
+    /// Decrypt a message in one step and verify its authentication tag.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn open(cipher: Cipher, key: &[u8], nonce: &[u8], ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        Self::new(cipher, key, nonce)?.decrypt_last(ciphertext, tag)
+    }
+
+    #[inline]
+    fn block_len(&self) -> usize {
+        self.0.block_len as usize
+    }
+
+    /// The final block holds between one and block size bytes, unless the message is empty.
+    fn last_block_start(&self, len: usize) -> usize {
+        len.saturating_sub(1) / self.block_len() * self.block_len()
+    }
+}
+
+impl Drop for Ocb {
+    fn drop(&mut self) {
+        unsafe {
+            util::zeromem(&mut self.0);
+        }
+    }
+}
+
+/// ChaCha20-Poly1305 (RFC 8439), authenticated encryption with the ChaCha20 stream cipher and the Poly1305 MAC.
+///
+/// The additional authenticated data has to be added with [`add_aad`] before any data is encrypted or decrypted.
+/// After [`finish`], the state can be used for another message with [`reset`] or [`reset_rfc7905`], which keep the
+/// key.
+///
+/// [`add_aad`]: #method.add_aad
+/// [`finish`]: #method.finish
+/// [`reset`]: #method.reset
+/// [`reset_rfc7905`]: #method.reset_rfc7905
+#[derive(Clone)]
+pub struct ChaCha20Poly1305 {
+    state: ffi::chacha20poly1305_state,
+    // The state right after the key setup, finishing a message wipes the key from `state`.
+    initial: ffi::chacha20poly1305_state,
+}
+
+impl ChaCha20Poly1305 {
+    /// Create a new chacha20poly1305 state from a 32 byte key and a nonce.
+    ///
+    /// The nonce is 12 bytes long, or 8 bytes for the original construction with a 64 bit nonce. It must never be
+    /// reused with the same key.
+    pub fn new(key: &[u8], nonce: &[u8]) -> Result<Self> {
+        let mut res = Self::init(key)?;
+        res.reset(nonce)?;
+        Ok(res)
+    }
+
+    /// Create a new chacha20poly1305 state for a TLS record as described in RFC 7905.
+    ///
+    /// The nonce is the 12 byte IV of the connection, combined with the 64 bit sequence number of the record.
+    pub fn new_rfc7905(key: &[u8], iv: &[u8], sequence_number: u64) -> Result<Self> {
+        let mut res = Self::init(key)?;
+        res.reset_rfc7905(iv, sequence_number)?;
+        Ok(res)
+    }
+
+    /// Start a new message with the same key and a new nonce.
+    pub fn reset(&mut self, nonce: &[u8]) -> Result<()> {
+        if nonce.len() != 12 && nonce.len() != 8 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        self.state = self.initial;
+        unsafe {
+            tryt!(ffi::chacha20poly1305_setiv(&mut self.state, nonce.as_ptr(), nonce.len() as c_ulong));
+        }
+        Ok(())
+    }
+
+    /// Start a new TLS record with the same key as described in RFC 7905.
+    pub fn reset_rfc7905(&mut self, iv: &[u8], sequence_number: u64) -> Result<()> {
+        if iv.len() != 12 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        // `chacha20poly1305_setiv_rfc7905` stores the sequence number in little-endian instead of network byte
+        // order, so the nonce is derived here.
+        let mut nonce = [0; 12];
+        nonce.copy_from_slice(iv);
+        for (n, s) in nonce[4..].iter_mut().zip(&sequence_number.to_be_bytes()) {
+            *n ^= s;
+        }
+        self.reset(&nonce)
+    }
+
+    /// Add (public) data that influences the authentication tag but is not encrypted.
+    pub fn add_aad(&mut self, aad: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::chacha20poly1305_add_aad(&mut self.state, aad.as_ptr(), aad.len() as c_ulong));
+        }
+        Ok(())
+    }
+
+    /// Encrypts the given data in place.
+    pub fn encrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::chacha20poly1305_encrypt(
+                &mut self.state,
+                data.as_ptr(),
+                data.len() as c_ulong,
+                data.as_mut_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Encrypts the given data.
+    pub fn encrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.encrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Decrypts the given data in place.
+    ///
+    /// The plaintext is not authenticated until the tag from [`finish`](#method.finish) was compared.
+    pub fn decrypt_in_place(&mut self, data: &mut [u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::chacha20poly1305_decrypt(
+                &mut self.state,
+                data.as_ptr(),
+                data.len() as c_ulong,
+                data.as_mut_ptr()
+            ));
+        }
+        Ok(())
+    }
+
+    /// Decrypts the given data.
+    ///
+    /// The plaintext is not authenticated until the tag from [`finish`](#method.finish) was compared.
+    pub fn decrypt(&mut self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut res = data.to_vec();
+        self.decrypt_in_place(&mut res)?;
+        Ok(res)
+    }
+
+    /// Generate the 16 byte authentication tag.
+    pub fn finish(&mut self) -> Result<Vec<u8>> {
+        // Without any data, LibTomCrypt does not pad the additional authenticated data
+        if self.state.aadflg != 0 {
+            let padding = (16 - self.state.aadlen % 16) as usize % 16;
+            unsafe {
+                tryt!(ffi::poly1305_process(&mut self.state.poly, [0; 16].as_ptr(), padding as c_ulong));
+            }
+            self.state.aadflg = 0;
+        }
+
+        let mut res = vec![0; 16];
+        unsafe {
+            let mut len = res.len() as c_ulong;
+            tryt!(ffi::chacha20poly1305_done(&mut self.state, res.as_mut_ptr(), &mut len));
+            res.truncate(len as usize);
+        }
+        Ok(res)
+    }
+
+    /// Encrypt a message in one step and return the ciphertext and the 16 byte authentication tag.
+    pub fn seal(key: &[u8], nonce: &[u8], aad: Option<&[u8]>, plaintext: &[u8]) -> Result<(Vec<u8>, Vec<u8>)> {
+        let mut state = Self::new(key, nonce)?;
+        state.add_aad(aad.unwrap_or(&[]))?;
+        let ciphertext = state.encrypt(plaintext)?;
+        Ok((ciphertext, state.finish()?))
+    }
+
+    /// Decrypt a message in one step and verify its authentication tag.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    pub fn open(key: &[u8], nonce: &[u8], aad: Option<&[u8]>, ciphertext: &[u8], tag: &[u8]) -> Result<Vec<u8>> {
+        let mut state = Self::new(key, nonce)?;
+        state.add_aad(aad.unwrap_or(&[]))?;
+        let mut plaintext = state.decrypt(ciphertext)?;
+
+        if !util::compare_slices(&state.finish()?, tag) {
+            util::zeromem_slice(&mut plaintext);
+            return Err(Error::InvalidTag);
+        }
+        Ok(plaintext)
+    }
+
+    fn init(key: &[u8]) -> Result<Self> {
+        if key.len() != 32 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_KEYSIZE));
+        }
+
+        unsafe {
+            let mut state = mem::zeroed();
+            tryt!(ffi::chacha20poly1305_init(&mut state, key.as_ptr(), key.len() as c_ulong));
+            Ok(ChaCha20Poly1305 { state, initial: state })
+        }
+    }
+}
+
+impl Drop for ChaCha20Poly1305 {
+    fn drop(&mut self) {
+        unsafe {
+            util::zeromem(&mut self.state);
+            util::zeromem(&mut self.initial);
+        }
+    }
+}
+
+/// Authenticated encryption with associated data under a fixed key.
+///
+/// Every message is encrypted with a new nonce, which must never be reused with the same key. The trait is object
+/// safe, so the algorithm can be negotiated at runtime with [`AeadAlgorithm`] and used as a `Box<dyn Aead>`.
+///
+/// The trait doesn't require `Send` or `Sync` and [`GcmKey`] is neither, so a key can't be shared between threads.
+///
+/// [`AeadAlgorithm`]: enum.AeadAlgorithm.html
+/// [`GcmKey`]: struct.GcmKey.html
+pub trait Aead {
+    /// Get the length of the key in bytes.
+    fn key_size(&self) -> usize;
+
+    /// Get the length of the nonce in bytes.
+    fn nonce_size(&self) -> usize;
+
+    /// Get the length of the authentication tag in bytes.
+    fn tag_size(&self) -> usize;
+
+    /// Encrypt the given buffer in place and return the authentication tag.
+    fn seal_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8]) -> Result<Vec<u8>>;
+
+    /// Decrypt the given buffer in place and verify the authentication tag.
+    ///
+    /// If the tag does not match, the buffer is zeroed and [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    fn open_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<()>;
+
+    /// Encrypt the given plaintext and return the ciphertext with the authentication tag appended.
+    fn seal(&self, nonce: &[u8], aad: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut res = plaintext.to_vec();
+        let tag = self.seal_in_place_detached(nonce, aad, &mut res)?;
+        res.extend(tag);
+        Ok(res)
+    }
+
+    /// Decrypt a ciphertext with the authentication tag appended and return the plaintext.
+    ///
+    /// The plaintext is only returned if the tag matches, otherwise [`Error::InvalidTag`] is returned.
+    ///
+    /// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+    fn open(&self, nonce: &[u8], aad: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        if ciphertext.len() < self.tag_size() {
+            return Err(Error::InvalidTag);
+        }
+
+        let (ciphertext, tag) = ciphertext.split_at(ciphertext.len() - self.tag_size());
+        let mut res = ciphertext.to_vec();
+        self.open_in_place_detached(nonce, aad, &mut res, tag)?;
+        Ok(res)
+    }
+}
+
+/// An authenticated encryption algorithm that can be selected at runtime.
+///
+/// All algorithms use a 16 byte tag and a 12 byte nonce, except EAX, which uses a nonce as long as the block size.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum AeadAlgorithm {
+    /// EAX mode with the given cipher, see [`EaxKey`](struct.EaxKey.html).
+    Eax(Cipher),
+    /// GCM with the given cipher, see [`GcmKey`](struct.GcmKey.html).
+    Gcm(Cipher),
+    /// CCM with the given cipher, see [`CcmKey`](struct.CcmKey.html).
+    Ccm(Cipher),
+    /// OCB3 with the given cipher, see [`Ocb3Key`](struct.Ocb3Key.html).
+    Ocb3(Cipher),
+    /// ChaCha20-Poly1305, see [`ChaCha20Poly1305Key`](struct.ChaCha20Poly1305Key.html).
+    ChaCha20Poly1305,
+}
+
+impl AeadAlgorithm {
+    /// Create an instance of this algorithm with the given key.
+    ///
+    /// The returned key is neither `Send` nor `Sync`, create one key per thread instead.
+    pub fn with_key(&self, key: &[u8]) -> Result<Box<dyn Aead>> {
+        Ok(match *self {
+            AeadAlgorithm::Eax(cipher) => Box::new(EaxKey::new(cipher, key, 16)?),
+            AeadAlgorithm::Gcm(cipher) => Box::new(GcmKey::new(cipher, key, 16)?),
+            AeadAlgorithm::Ccm(cipher) => Box::new(CcmKey::new(cipher, key, 16)?),
+            AeadAlgorithm::Ocb3(cipher) => Box::new(Ocb3Key::new(cipher, key, 16)?),
+            AeadAlgorithm::ChaCha20Poly1305 => Box::new(ChaCha20Poly1305Key::new(key)?),
+        })
+    }
+
+    /// Get the length of the nonce in bytes.
+    pub fn nonce_size(&self) -> usize {
+        match *self {
+            AeadAlgorithm::Eax(cipher) => cipher.block_size(),
+            _ => 12,
+        }
+    }
+
+    /// Get the length of the authentication tag in bytes.
+    pub fn tag_size(&self) -> usize {
+        16
+    }
+}
+
+/// Key material shared by the [`Aead`](trait.Aead.html) implementations, which is zeroed when it is dropped.
+#[derive(Clone)]
+struct AeadKey {
+    cipher: Cipher,
+    key: Vec<u8>,
+    tag_len: usize,
+}
+
+impl AeadKey {
+    fn check_nonce(&self, nonce: &[u8], nonce_len: usize) -> Result<()> {
+        if nonce.len() != nonce_len {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for AeadKey {
+    fn drop(&mut self) {
+        util::zeromem_slice(&mut self.key);
+    }
+}
+
+/// Compare a computed tag with the received tag and zero the plaintext if they do not match.
+fn verify_tag(computed: &[u8], tag: &[u8], plaintext: &mut [u8]) -> Result<()> {
+    if !util::compare_slices(computed, tag) {
+        util::zeromem_slice(plaintext);
+        return Err(Error::InvalidTag);
+    }
+    Ok(())
+}
+
+/// [`EaxState`](struct.EaxState.html) with a fixed key, using nonces as long as the block size of the cipher.
+#[derive(Clone)]
+pub struct EaxKey(AeadKey);
+
+impl EaxKey {
+    /// Create a key for the given cipher with a tag length of up to the block size.
+    pub fn new(cipher: Cipher, key: &[u8], tag_len: usize) -> Result<Self> {
+        if tag_len == 0 || tag_len > cipher.block_size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+        EaxState::new(cipher, key, &vec![0; cipher.block_size()], None)?;
+
+        Ok(EaxKey(AeadKey { cipher, key: key.to_vec(), tag_len }))
+    }
+}
+
+impl Aead for EaxKey {
+    fn key_size(&self) -> usize {
+        self.0.key.len()
+    }
+
+    fn nonce_size(&self) -> usize {
+        self.0.cipher.block_size()
+    }
+
+    fn tag_size(&self) -> usize {
+        self.0.tag_len
+    }
+
+    fn seal_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8]) -> Result<Vec<u8>> {
+        self.0.check_nonce(nonce, self.nonce_size())?;
+        let mut eax = EaxState::new(self.0.cipher, &self.0.key, nonce, Some(aad))?;
+        eax.encrypt_in_place(buffer)?;
+        eax.finish(self.0.tag_len)
+    }
+
+    fn open_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<()> {
+        self.0.check_nonce(nonce, self.nonce_size())?;
+        let mut eax = EaxState::new(self.0.cipher, &self.0.key, nonce, Some(aad))?;
+        eax.decrypt_in_place(buffer)?;
+        verify_tag(&eax.finish(self.0.tag_len)?, tag, buffer)
+    }
+}
+
+/// [`Gcm`](struct.Gcm.html) with a fixed key, using 12 byte nonces.
+///
+/// The keyed state is kept and a copy of it is [`reset`](struct.Gcm.html#method.reset) for every message, so the key
+/// schedule and the GHASH tables are only computed once.
+///
+/// Unlike the other keys, `GcmKey` is neither `Send` nor `Sync`, because the cipher key schedule of LibTomCrypt
+/// contains a raw pointer. Create one key per thread instead.
+#[derive(Clone)]
+pub struct GcmKey {
+    gcm: Gcm,
+    key_len: usize,
+    tag_len: usize,
+}
+
+impl GcmKey {
+    /// Create a key for the given cipher with a tag length between 4 and 16 bytes.
+    pub fn new(cipher: Cipher, key: &[u8], tag_len: usize) -> Result<Self> {
+        if !(4..=16).contains(&tag_len) {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+        let gcm = Gcm::new(cipher, key, &[0; 12])?;
+
+        Ok(GcmKey { gcm, key_len: key.len(), tag_len })
+    }
+
+    fn start(&self, nonce: &[u8], aad: &[u8]) -> Result<Gcm> {
+        if nonce.len() != self.nonce_size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let mut gcm = self.gcm.clone();
+        gcm.reset(nonce)?;
+        gcm.add_aad(aad)?;
+        Ok(gcm)
+    }
+}
+
+impl Aead for GcmKey {
+    fn key_size(&self) -> usize {
+        self.key_len
+    }
+
+    fn nonce_size(&self) -> usize {
+        12
+    }
+
+    fn tag_size(&self) -> usize {
+        self.tag_len
+    }
+
+    fn seal_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8]) -> Result<Vec<u8>> {
+        let mut gcm = self.start(nonce, aad)?;
+        gcm.encrypt_in_place(buffer)?;
+        gcm.finish(self.tag_len)
+    }
+
+    fn open_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<()> {
+        let mut gcm = self.start(nonce, aad)?;
+        gcm.decrypt_in_place(buffer)?;
+        verify_tag(&gcm.finish(self.tag_len)?, tag, buffer)
+    }
+}
+
+/// [`Ccm`](struct.Ccm.html) with a fixed key, using 12 byte nonces.
+///
+/// The nonce length limits the payload to 16 MiB.
+#[derive(Clone)]
+pub struct CcmKey(AeadKey);
+
+impl CcmKey {
+    /// Create a key for the given cipher with an even tag length between 4 and 16 bytes.
+    pub fn new(cipher: Cipher, key: &[u8], tag_len: usize) -> Result<Self> {
+        Ccm::new(cipher, key, &[0; 12], 0, 0, tag_len)?;
+
+        Ok(CcmKey(AeadKey { cipher, key: key.to_vec(), tag_len }))
+    }
+
+    fn start(&self, nonce: &[u8], aad: &[u8], payload_len: usize) -> Result<Ccm<CcmPayload>> {
+        self.0.check_nonce(nonce, self.nonce_size())?;
+        let mut ccm = Ccm::new(self.0.cipher, &self.0.key, nonce, aad.len(), payload_len, self.0.tag_len)?;
+        ccm.add_aad(aad)?;
+        ccm.start().map_err(|(_, e)| e)
+    }
+}
+
+impl Aead for CcmKey {
+    fn key_size(&self) -> usize {
+        self.0.key.len()
+    }
+
+    fn nonce_size(&self) -> usize {
+        12
+    }
+
+    fn tag_size(&self) -> usize {
+        self.0.tag_len
+    }
+
+    fn seal_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8]) -> Result<Vec<u8>> {
+        let mut ccm = self.start(nonce, aad, buffer.len())?;
+        ccm.encrypt_in_place(buffer)?;
+        ccm.finish().map_err(|(_, e)| e)
+    }
+
+    fn open_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<()> {
+        let mut ccm = self.start(nonce, aad, buffer.len())?;
+        ccm.decrypt_in_place(buffer)?;
+        verify_tag(&ccm.finish().map_err(|(_, e)| e)?, tag, buffer)
+    }
+}
+
+/// [`Ocb3`](struct.Ocb3.html) with a fixed key, using 12 byte nonces.
+#[derive(Clone)]
+pub struct Ocb3Key(AeadKey);
+
+impl Ocb3Key {
+    /// Create a key for the given cipher with a tag length between 1 and 16 bytes.
+    pub fn new(cipher: Cipher, key: &[u8], tag_len: usize) -> Result<Self> {
+        Ocb3::new(cipher, key, &[0; 12], tag_len)?;
+
+        Ok(Ocb3Key(AeadKey { cipher, key: key.to_vec(), tag_len }))
+    }
+
+    fn start(&self, nonce: &[u8], aad: &[u8]) -> Result<Ocb3> {
+        self.0.check_nonce(nonce, self.nonce_size())?;
+        let mut ocb = Ocb3::new(self.0.cipher, &self.0.key, nonce, self.0.tag_len)?;
+        ocb.add_aad(aad)?;
+        Ok(ocb)
+    }
+}
+
+impl Aead for Ocb3Key {
+    fn key_size(&self) -> usize {
+        self.0.key.len()
+    }
+
+    fn nonce_size(&self) -> usize {
+        12
+    }
+
+    fn tag_size(&self) -> usize {
+        self.0.tag_len
+    }
+
+    fn seal_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8]) -> Result<Vec<u8>> {
+        self.start(nonce, aad)?.encrypt_last_in_place(buffer)
+    }
+
+    fn open_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<()> {
+        self.start(nonce, aad)?.decrypt_last_in_place(buffer, tag)
+    }
+}
+
+/// [`ChaCha20Poly1305`](struct.ChaCha20Poly1305.html) with a fixed 32 byte key, using 12 byte nonces.
+#[derive(Clone)]
+pub struct ChaCha20Poly1305Key(ChaCha20Poly1305);
+
+impl ChaCha20Poly1305Key {
+    /// Create a key from 32 bytes.
+    pub fn new(key: &[u8]) -> Result<Self> {
+        // The key schedule is kept in the state and reused for every message
+        Ok(ChaCha20Poly1305Key(ChaCha20Poly1305::init(key)?))
+    }
+
+    fn start(&self, nonce: &[u8], aad: &[u8]) -> Result<ChaCha20Poly1305> {
+        if nonce.len() != self.nonce_size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let mut state = self.0.clone();
+        state.reset(nonce)?;
+        state.add_aad(aad)?;
+        Ok(state)
+    }
+}
+
+impl Aead for ChaCha20Poly1305Key {
+    fn key_size(&self) -> usize {
+        32
+    }
+
+    fn nonce_size(&self) -> usize {
+        12
+    }
+
+    fn tag_size(&self) -> usize {
+        16
+    }
+
+    fn seal_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8]) -> Result<Vec<u8>> {
+        let mut state = self.start(nonce, aad)?;
+        state.encrypt_in_place(buffer)?;
+        state.finish()
+    }
+
+    fn open_in_place_detached(&self, nonce: &[u8], aad: &[u8], buffer: &mut [u8], tag: &[u8]) -> Result<()> {
+        let mut state = self.start(nonce, aad)?;
+        state.decrypt_in_place(buffer)?;
+        verify_tag(&state.finish()?, tag, buffer)
+    }
+}
+
+fn slice_ptr(data: Option<&[u8]>) -> (*const c_uchar, c_ulong) {
+    match data {
+        Some(data) => (data.as_ptr(), data.len() as c_ulong),
+        None => (ptr::null(), 0),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex;
+    use super::*;
+
+    #[test]
+    fn test_eax_loop() {
+        let key = [1; 16];
+        let nonce = [2; 16];
+        let header = [3; 3];
+        let data = [4; 10];
+        let tag_len = 7;
+
+        // Encrypt
+        let mut eax = EaxState::new(Cipher::aes(), &key, &nonce, Some(&header))
+            .unwrap();
+        let enc = eax.encrypt(&data).unwrap();
+        let tag = eax.finish(tag_len).unwrap();
+
+        // Decrypt
+        let mut eax = EaxState::new(Cipher::aes(), &key, &nonce, Some(&header))
+            .unwrap();
+        let dec = eax.decrypt(&enc).unwrap();
+        let tag2 = eax.finish(tag_len).unwrap();
+
+        assert_eq!(tag, tag2);
+        assert_eq!(&data, dec.as_slice());
+    }
+
+    #[test]
+    fn test_eax_seal_open() {
+        // Test vector from the EAX paper
+        let key = hex::decode("91945d3f4dcbee0bf45ef52255f095a4").unwrap();
+        let nonce = hex::decode("becaf043b0a23d843194ba972c66debd").unwrap();
+        let header = hex::decode("fa3bfd4806eb53fa").unwrap();
+        let pt = [0xf7, 0xfb];
+        let ct = [0x19, 0xdd];
+        let tag = hex::decode("5c4c9331049d0bdab0277408f67967e5").unwrap();
+
+        let (enc, tag2) = EaxState::seal(Cipher::aes(), &key, &nonce, Some(&header), &pt, 16).unwrap();
+        assert_eq!(enc, ct);
+        assert_eq!(tag2, tag);
+        assert_eq!(EaxState::open(Cipher::aes(), &key, &nonce, Some(&header), &ct, &tag).unwrap(), pt);
+        assert_eq!(EaxState::open(Cipher::aes(), &key, &nonce, Some(&header), &ct, &tag[..8]).unwrap(), pt);
+
+        match EaxState::open(Cipher::aes(), &key, &nonce, None, &ct, &tag) {
+            Err(Error::InvalidTag) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        assert!(EaxState::open(Cipher::aes(), &key, &nonce, Some(&header), &[0x19, 0xdc], &tag).is_err());
+        assert!(EaxState::open(Cipher::aes(), &key, &nonce, Some(&header), &ct, &[]).is_err());
+
+        // Streaming and one-shot are compatible
+        let mut eax = EaxState::new(Cipher::aes(), &key, &nonce, Some(&header)).unwrap();
+        assert_eq!(eax.encrypt(&pt).unwrap(), ct);
+        assert_eq!(eax.finish(16).unwrap(), tag);
+    }
+
+    // Test case 4 from the GCM specification
+    const GCM_KEY: &str = "feffe9928665731c6d6a8f9467308308";
+    const GCM_IV: &str = "cafebabefacedbaddecaf888";
+    const GCM_AAD: &str = "feedfacedeadbeeffeedfacedeadbeefabaddad2";
+    const GCM_PT: &str = "d9313225f88406e5a55909c5aff5269a86a7a9531534f7da2e4c303d8a318a721c3c0c95956809532fcf0e2449a6\
+        b525b16aedf5aa0de657ba637b39";
+    const GCM_CT: &str = "42831ec2217774244b7221b784d0d49ce3aa212f2c02a4e035c17e2329aca12e21d514b25466931c7d8f6a5aac84\
+        aa051ba30b396a0aac973d58e091";
+    const GCM_TAG: &str = "5bc94fbc3221a5db94fae95ae7121a47";
+
+    #[test]
+    fn test_gcm_vector() {
+        let key = hex::decode(GCM_KEY).unwrap();
+        let iv = hex::decode(GCM_IV).unwrap();
+        let aad = hex::decode(GCM_AAD).unwrap();
+        let pt = hex::decode(GCM_PT).unwrap();
+        let ct = hex::decode(GCM_CT).unwrap();
+        let tag = hex::decode(GCM_TAG).unwrap();
+
+        // Streaming, split into odd chunks
+        let mut gcm = Gcm::new(Cipher::aes(), &key, &iv).unwrap();
+        gcm.add_aad(&aad[..7]).unwrap();
+        gcm.add_aad(&aad[7..]).unwrap();
+        let mut enc = gcm.encrypt(&pt[..17]).unwrap();
+        enc.extend(gcm.encrypt(&pt[17..]).unwrap());
+        assert_eq!(enc, ct);
+        assert_eq!(gcm.finish(16).unwrap(), tag);
+
+        // Reuse the state
+        gcm.reset(&iv).unwrap();
+        gcm.add_aad(&aad).unwrap();
+        let mut dec = ct.clone();
+        gcm.decrypt_in_place(&mut dec).unwrap();
+        assert_eq!(dec, pt);
+        assert_eq!(gcm.finish(12).unwrap(), &tag[..12]);
+
+        // One-shot
+        let (enc, tag2) = Gcm::seal(Cipher::aes(), &key, &iv, Some(&aad), &pt, 16).unwrap();
+        assert_eq!(enc, ct);
+        assert_eq!(tag2, tag);
+        assert_eq!(Gcm::open(Cipher::aes(), &key, &iv, Some(&aad), &ct, &tag).unwrap(), pt);
+    }
+
+    #[test]
+    fn test_gcm_open_invalid() {
+        let key = [1; 16];
+        let iv = [2; 12];
+        let data = [4; 40];
+        let (ct, tag) = Gcm::seal(Cipher::aes(), &key, &iv, None, &data, 16).unwrap();
+        assert_eq!(Gcm::open(Cipher::aes(), &key, &iv, None, &ct, &tag).unwrap(), &data[..]);
+
+        let mut modified = ct.clone();
+        modified[3] ^= 1;
+        match Gcm::open(Cipher::aes(), &key, &iv, None, &modified, &tag) {
+            Err(Error::InvalidTag) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        assert!(Gcm::open(Cipher::aes(), &key, &iv, Some(&[1]), &ct, &tag).is_err());
+        assert!(Gcm::open(Cipher::aes(), &key, &[3; 12], None, &ct, &tag).is_err());
+        assert!(Gcm::open(Cipher::aes(), &key, &iv, None, &ct, &tag[..3]).is_err());
+
+        // AAD is not allowed after data
+        let mut gcm = Gcm::new(Cipher::aes(), &key, &iv).unwrap();
+        gcm.encrypt(&data).unwrap();
+        assert!(gcm.add_aad(&[1]).is_err());
+    }
+
+    #[test]
+    fn test_ccm_vector() {
+        // RFC 3610, packet vector #1
+        let key: Vec<u8> = (0xc0..0xd0).collect();
+        let nonce = hex::decode("00000003020100a0a1a2a3a4a5").unwrap();
+        let aad: Vec<u8> = (0..8).collect();
+        let pt: Vec<u8> = (8..0x1f).collect();
+        let ct = hex::decode("588c979a61c663d2f066d0c2c0f989806d5f6b61dac384").unwrap();
+        let tag = hex::decode("17e8d12cfdf926e0").unwrap();
+
+        let mut ccm = Ccm::new(Cipher::aes(), &key, &nonce, aad.len(), pt.len(), tag.len()).unwrap();
+        ccm.add_aad(&aad[..3]).unwrap();
+        ccm.add_aad(&aad[3..]).unwrap();
+        let mut ccm = ccm.start().unwrap();
+        let mut enc = ccm.encrypt(&pt[..10]).unwrap();
+        enc.extend(ccm.encrypt(&pt[10..]).unwrap());
+        assert_eq!(enc, ct);
+        assert_eq!(ccm.finish().unwrap(), tag);
+
+        let mut ccm = Ccm::new(Cipher::aes(), &key, &nonce, aad.len(), pt.len(), tag.len()).unwrap();
+        ccm.add_aad(&aad).unwrap();
+        let mut ccm = ccm.start().unwrap();
+        let mut dec = ct.clone();
+        ccm.decrypt_in_place(&mut dec).unwrap();
+        assert_eq!(dec, pt);
+        assert_eq!(ccm.finish().unwrap(), tag);
+
+        let (enc, tag2) = Ccm::seal(Cipher::aes(), &key, &nonce, Some(&aad), &pt, tag.len()).unwrap();
+        assert_eq!(enc, ct);
+        assert_eq!(tag2, tag);
+        assert_eq!(Ccm::open(Cipher::aes(), &key, &nonce, Some(&aad), &ct, &tag).unwrap(), pt);
+
+        let mut modified = tag.clone();
+        modified[0] ^= 1;
+        match Ccm::open(Cipher::aes(), &key, &nonce, Some(&aad), &ct, &modified) {
+            Err(Error::InvalidTag) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+    }
+
+    #[test]
+    fn test_ccm_lengths() {
+        let key = [1; 16];
+        let nonce = [2; 12];
+
+        // Too much and too little AAD
+        let mut ccm = Ccm::new(Cipher::aes(), &key, &nonce, 4, 8, 16).unwrap();
+        match ccm.add_aad(&[0; 5]) {
+            Err(Error::LengthMismatch { expected: 4, actual: 5 }) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        ccm.add_aad(&[0; 3]).unwrap();
+        let mut ccm = match ccm.start() {
+            Err((ccm, Error::LengthMismatch { expected: 4, actual: 3 })) => ccm,
+            r => panic!("Unexpected result {:?}", r),
+        };
+
+        // Too much and too little payload
+        ccm.add_aad(&[0; 1]).unwrap();
+        let mut ccm = ccm.start().unwrap();
+        assert!(ccm.encrypt(&[0; 9]).is_err());
+        ccm.encrypt(&[0; 7]).unwrap();
+        let mut ccm = match ccm.finish() {
+            Err((ccm, Error::LengthMismatch { expected: 8, actual: 7 })) => ccm,
+            r => panic!("Unexpected result {:?}", r),
+        };
+        ccm.encrypt(&[0; 1]).unwrap();
+        assert_eq!(ccm.finish().unwrap().len(), 16);
+
+        // Invalid parameters
+        assert!(Ccm::new(Cipher::aes(), &key, &[0; 6], 0, 0, 16).is_err());
+        assert!(Ccm::new(Cipher::aes(), &key, &[0; 14], 0, 0, 16).is_err());
+        assert!(Ccm::new(Cipher::aes(), &key, &nonce, 0, 0, 5).is_err());
+        assert!(Ccm::new(Cipher::aes(), &key, &nonce, 0, 0, 18).is_err());
+        // A 13 byte nonce leaves two bytes for the payload length
+        assert!(Ccm::new(Cipher::aes(), &key, &[0; 13], 0, 0x10000, 16).is_err());
+        assert!(Ccm::new(Cipher::aes(), &key, &[0; 13], 0, 0xffff, 16).is_ok());
+    }
+
+    #[test]
+    fn test_ocb3_vector() {
+        // RFC 7253, appendix A
+        let key: Vec<u8> = (0..16).collect();
+        let nonce = hex::decode("bbaa9988776655443322110d").unwrap();
+        let data: Vec<u8> = (0..40).collect();
+        let ct = hex::decode("d5ca91748410c1751ff8a2f618255b68a0a12e093ff454606e59f9c1d0ddc54b65e8628e568bad7a")
+            .unwrap();
+        let tag = hex::decode("ed07ba06a4a69483a7035490c5769e60").unwrap();
+
+        let mut ocb = Ocb3::new(Cipher::aes(), &key, &nonce, 16).unwrap();
+        ocb.add_aad(&data[..5]).unwrap();
+        let mut enc = ocb.encrypt(&data[..16]).unwrap();
+        ocb.add_aad(&data[5..]).unwrap();
+        let (last, tag2) = ocb.encrypt_last(&data[16..]).unwrap();
+        enc.extend(last);
+        assert_eq!(enc, ct);
+        assert_eq!(tag2, tag);
+
+        let mut ocb = Ocb3::new(Cipher::aes(), &key, &nonce, 16).unwrap();
+        ocb.add_aad(&data).unwrap();
+        let mut dec = ct.clone();
+        ocb.decrypt_in_place(&mut dec[..32]).unwrap();
+        ocb.decrypt_last_in_place(&mut dec[32..], &tag).unwrap();
+        assert_eq!(dec, data);
+
+        // Whole blocks are not allowed to be partial
+        let mut ocb = Ocb3::new(Cipher::aes(), &key, &nonce, 16).unwrap();
+        assert!(ocb.encrypt(&data[..17]).is_err());
+        let mut enc = data.clone();
+        assert!(ocb.encrypt_in_place(&mut enc[..17]).is_err());
+        assert_eq!(enc, data);
+        ocb.encrypt_in_place(&mut enc[..32]).unwrap();
+        ocb.add_aad(&data).unwrap();
+        assert_eq!(ocb.encrypt_last_in_place(&mut enc[32..]).unwrap(), tag);
+        assert_eq!(enc, ct);
+
+        // One-shot with a shorter tag
+        let nonce = hex::decode("bbaa99887766554433221105").unwrap();
+        let expected = hex::decode("76992f2e5ca61d32c767d4908c3395e7fe8a19362027f949").unwrap();
+        let (enc, tag) = Ocb3::seal(Cipher::aes(), &key, &nonce, Some(&data[..8]), &data[..8], 16).unwrap();
+        assert_eq!(&enc[..], &expected[..8]);
+        assert_eq!(&tag[..], &expected[8..]);
+        assert_eq!(Ocb3::open(Cipher::aes(), &key, &nonce, Some(&data[..8]), &enc, &tag).unwrap(), &data[..8]);
+
+        let (enc, tag) = Ocb3::seal(Cipher::aes(), &key, &nonce, None, &data, 12).unwrap();
+        assert_eq!(tag.len(), 12);
+        let ocb = Ocb3::new(Cipher::aes(), &key, &nonce, 12).unwrap();
+        assert_eq!(ocb.decrypt_last(&enc, &tag).unwrap(), data);
+        match Ocb3::open(Cipher::aes(), &key, &nonce, Some(&[1]), &enc, &tag) {
+            Err(Error::InvalidTag) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        let ocb = Ocb3::new(Cipher::aes(), &key, &nonce, 12).unwrap();
+        assert!(ocb.decrypt_last(&enc, &tag[..11]).is_err());
+        assert!(Ocb3::open(Cipher::aes(), &key, &nonce, None, &enc, &[]).is_err());
+        assert!(Ocb3::new(Cipher::aes(), &key, &[0; 16], 16).is_err());
+        assert!(Ocb3::new(Cipher::aes(), &key, &nonce, 17).is_err());
+    }
+
+    #[test]
+    fn test_ocb_vector() {
+        // OCB-AES-128-32B from the LibTomCrypt self-test
+        let key: Vec<u8> = (0..16).collect();
+        let mut nonce = [0; 16];
+        nonce[15] = 1;
+        let pt: Vec<u8> = (0..32).collect();
+        let ct = hex::decode("01a075f0d815b1a4e9c881a1bcffc3eb4afcbb7fedc08ca8654c6d304d1612fa").unwrap();
+        let tag = hex::decode("c14cbf2c1a1f1c3c137eadea1f2f2fcf").unwrap();
+
+        let mut ocb = Ocb::new(Cipher::aes(), &key, &nonce).unwrap();
+        let mut enc = ocb.encrypt(&pt[..16]).unwrap();
+        let (last, tag2) = ocb.encrypt_last(&pt[16..], 16).unwrap();
+        enc.extend(last);
+        assert_eq!(enc, ct);
+        assert_eq!(tag2, tag);
+
+        let (enc, tag2) = Ocb::seal(Cipher::aes(), &key, &nonce, &pt, 16).unwrap();
+        assert_eq!(enc, ct);
+        assert_eq!(tag2, tag);
+        assert_eq!(Ocb::open(Cipher::aes(), &key, &nonce, &ct, &tag).unwrap(), pt);
+        assert_eq!(Ocb::open(Cipher::aes(), &key, &nonce, &ct, &tag[..8]).unwrap(), pt);
+
+        // OCB-AES-128-3B
+        let (enc, tag) = Ocb::seal(Cipher::aes(), &key, &nonce, &[0, 1, 2], 16).unwrap();
+        assert_eq!(enc, [0xfc, 0xd3, 0x7d]);
+        assert_eq!(tag, hex::decode("02254739a5e3565ae2dcd62c659746ba").unwrap());
+
+        let mut modified = ct.clone();
+        modified[20] ^= 1;
+        match Ocb::open(Cipher::aes(), &key, &nonce, &modified, &tag) {
+            Err(Error::InvalidTag) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        assert!(Ocb::open(Cipher::aes(), &key, &nonce, &ct, &[]).is_err());
+        assert!(Ocb::new(Cipher::aes(), &key, &nonce[..12]).is_err());
+    }
+
+    #[test]
+    fn test_chacha20poly1305_vector() {
+        // RFC 8439, section 2.8.2
+        let key: Vec<u8> = (0x80..0xa0).collect();
+        let nonce = hex::decode("070000004041424344454647").unwrap();
+        let aad = hex::decode("50515253c0c1c2c3c4c5c6c7").unwrap();
+        let pt = &b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, \
+            sunscreen would be it."[..];
+        let ct = hex::decode("d31a8d34648e60db7b86afbc53ef7ec2a4aded51296e08fea9e2b5a736ee62d63dbea45e8ca9671282fafb69\
+            da92728b1a71de0a9e060b2905d6a5b67ecd3b3692ddbd7f2d778b8c9803aee328091b58fab324e4fad675945585808b4831d7bc3f\
+            f4def08e4b7a9de576d26586cec64b6116").unwrap();
+        let tag = hex::decode("1ae10b594f09e26a7e902ecbd0600691").unwrap();
+
+        let mut state = ChaCha20Poly1305::new(&key, &nonce).unwrap();
+        state.add_aad(&aad[..5]).unwrap();
+        state.add_aad(&aad[5..]).unwrap();
+        let mut enc = state.encrypt(&pt[..33]).unwrap();
+        enc.extend(state.encrypt(&pt[33..]).unwrap());
+        assert_eq!(enc, ct);
+        assert_eq!(state.finish().unwrap(), tag);
+
+        // Reuse the key
+        state.reset(&nonce).unwrap();
+        state.add_aad(&aad).unwrap();
+        let mut dec = ct.clone();
+        state.decrypt_in_place(&mut dec).unwrap();
+        assert_eq!(dec, pt);
+        assert_eq!(state.finish().unwrap(), tag);
+
+        let (enc, tag2) = ChaCha20Poly1305::seal(&key, &nonce, Some(&aad), pt).unwrap();
+        assert_eq!(enc, ct);
+        assert_eq!(tag2, tag);
+        assert_eq!(ChaCha20Poly1305::open(&key, &nonce, Some(&aad), &ct, &tag).unwrap(), pt);
+        match ChaCha20Poly1305::open(&key, &nonce, None, &ct, &tag) {
+            Err(Error::InvalidTag) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+
+        // Only additional authenticated data
+        let (enc, tag) = ChaCha20Poly1305::seal(&key, &nonce, Some(b"abc"), &[]).unwrap();
+        assert!(enc.is_empty());
+        assert_eq!(tag, hex::decode("474812b5a28c34cc582c3fd32a501144").unwrap());
+
+        assert!(ChaCha20Poly1305::new(&key[..16], &nonce).is_err());
+        assert!(ChaCha20Poly1305::new(&key, &nonce[..10]).is_err());
+        assert!(ChaCha20Poly1305::new(&key, &nonce[..8]).is_ok());
+    }
+
+    #[test]
+    fn test_chacha20poly1305_rfc7905() {
+        let key: Vec<u8> = (0x80..0xa0).collect();
+        let iv: Vec<u8> = (1..13).collect();
+        let sequence_number = 0x1122334455667788;
+        let nonce = hex::decode("010203041424344c5c6c7c84").unwrap();
+
+        let mut state = ChaCha20Poly1305::new_rfc7905(&key, &iv, sequence_number).unwrap();
+        state.add_aad(b"hdr").unwrap();
+        assert_eq!(state.encrypt(b"record").unwrap(), hex::decode("9190e828a83e").unwrap());
+        let tag = state.finish().unwrap();
+        assert_eq!(tag, hex::decode("f591c327cead722c4eb1053d67ad69be").unwrap());
+        assert_eq!(ChaCha20Poly1305::seal(&key, &nonce, Some(b"hdr"), b"record").unwrap().1, tag);
+
+        state.reset_rfc7905(&iv, sequence_number + 1).unwrap();
+        state.add_aad(b"hdr").unwrap();
+        assert_ne!(state.encrypt(b"record").unwrap(), hex::decode("9190e828a83e").unwrap());
+        assert!(state.reset_rfc7905(&iv[..8], 0).is_err());
+    }
+
+    #[test]
+    fn test_aead_algorithms() {
+        let algorithms = [
+            AeadAlgorithm::Eax(Cipher::aes()),
+            AeadAlgorithm::Gcm(Cipher::aes()),
+            AeadAlgorithm::Ccm(Cipher::aes()),
+            AeadAlgorithm::Ocb3(Cipher::aes()),
+            AeadAlgorithm::ChaCha20Poly1305,
+        ];
+        let data: Vec<u8> = (0..50).collect();
+
+        for algorithm in &algorithms {
+            let aead = algorithm.with_key(&[7; 32]).unwrap();
+            assert_eq!(aead.key_size(), 32);
+            assert_eq!(aead.nonce_size(), algorithm.nonce_size());
+            assert_eq!(aead.tag_size(), algorithm.tag_size());
+
+            let nonce = vec![3; aead.nonce_size()];
+            let sealed = aead.seal(&nonce, b"header", &data).unwrap();
+            assert_eq!(sealed.len(), data.len() + aead.tag_size());
+            assert_eq!(aead.open(&nonce, b"header", &sealed).unwrap(), data);
+
+            let mut buffer = data.clone();
+            let tag = aead.seal_in_place_detached(&nonce, b"header", &mut buffer).unwrap();
+            assert_eq!(&buffer[..], &sealed[..data.len()]);
+            assert_eq!(tag, &sealed[data.len()..]);
+            aead.open_in_place_detached(&nonce, b"header", &mut buffer, &tag).unwrap();
+            assert_eq!(buffer, data);
+
+            let mut modified = sealed.clone();
+            modified[0] ^= 1;
+            match aead.open(&nonce, b"header", &modified) {
+                Err(Error::InvalidTag) => {}
+                r => panic!("Unexpected result for {:?}: {:?}", algorithm, r),
+            }
+            match aead.open(&nonce, b"other", &sealed) {
+                Err(Error::InvalidTag) => {}
+                r => panic!("Unexpected result for {:?}: {:?}", algorithm, r),
+            }
+            assert!(aead.open(&nonce, b"header", &sealed[..10]).is_err());
+            assert!(aead.seal(&nonce[1..], b"header", &data).is_err());
+        }
+
+        assert!(AeadAlgorithm::ChaCha20Poly1305.with_key(&[7; 16]).is_err());
+        assert!(AeadAlgorithm::Gcm(Cipher::aes()).with_key(&[7; 15]).is_err());
+    }
+
+    #[test]
+    fn test_aead_vectors() {
+        let aead = GcmKey::new(Cipher::aes(), &hex::decode(GCM_KEY).unwrap(), 16).unwrap();
+        let mut ct = hex::decode(GCM_CT).unwrap();
+        ct.extend(hex::decode(GCM_TAG).unwrap());
+        let pt = aead.open(&hex::decode(GCM_IV).unwrap(), &hex::decode(GCM_AAD).unwrap(), &ct).unwrap();
+        assert_eq!(pt, hex::decode(GCM_PT).unwrap());
+
+        // The keyed state is reset for every message, also after a failed one
+        let mut invalid = ct.clone();
+        invalid[0] ^= 1;
+        assert!(aead.open(&hex::decode(GCM_IV).unwrap(), &hex::decode(GCM_AAD).unwrap(), &invalid).is_err());
+        let sealed = aead.seal(&[3; 12], b"aad", b"message").unwrap();
+        assert_eq!(aead.clone().open(&[3; 12], b"aad", &sealed).unwrap(), b"message");
+        let (ct, tag) = Gcm::seal(Cipher::aes(), &hex::decode(GCM_KEY).unwrap(), &[3; 12], Some(b"aad"), b"message", 16)
+            .unwrap();
+        assert_eq!(sealed, [ct, tag].concat());
+
+        let key: Vec<u8> = (0xc0..0xd0).collect();
+        let aad: Vec<u8> = (0..8).collect();
+        let pt: Vec<u8> = (8..0x1f).collect();
+        let (ct, tag) = Ccm::seal(Cipher::aes(), &key, &[5; 12], Some(&aad), &pt, 8).unwrap();
+        let aead = CcmKey::new(Cipher::aes(), &key, 8).unwrap();
+        let mut buffer = pt.clone();
+        assert_eq!(aead.seal_in_place_detached(&[5; 12], &aad, &mut buffer).unwrap(), tag);
+        assert_eq!(buffer, ct);
+        // Only 12 byte nonces are accepted
+        assert!(aead.seal(&[5; 13], &aad, &pt).is_err());
+
+        let key: Vec<u8> = (0..16).collect();
+        let aead: Box<dyn Aead> = Box::new(Ocb3Key::new(Cipher::aes(), &key, 16).unwrap());
+        let nonce = hex::decode("bbaa99887766554433221105").unwrap();
+        let expected = hex::decode("76992f2e5ca61d32c767d4908c3395e7fe8a19362027f949").unwrap();
+        let data: Vec<u8> = (0..8).collect();
+        assert_eq!(aead.seal(&nonce, &data, &data).unwrap(), expected);
     }
 }
@@ -1,13 +1,131 @@
+use std::io::Read;
 use std::mem::{self, transmute};
 use std::os::raw::*;
 use std::ptr;
 
-use {ffi, rand, Result};
+use {ffi, internal, rand, util, Result};
+use aead::EaxState;
+use error::Error;
+use hash::Hash;
+use rand::Prng;
+use symmetric::Cipher;
 
 /// A private or public elliptic curve key.
 #[derive(Debug)]
 pub struct EccKey(ffi::ecc_key);
 
+/// An elliptic curve supported by LibTomCrypt.
+///
+/// All curves are short Weierstrass curves over a prime field with `a = -3`.
+#[derive(Clone, Copy, Debug, Eq, Hash, PartialEq)]
+pub enum Curve {
+    /// SECG secp112r1.
+    Secp112r1,
+    /// SECG secp128r1.
+    Secp128r1,
+    /// SECG secp160r1.
+    Secp160r1,
+    /// NIST P-192, also known as secp192r1.
+    P192,
+    /// NIST P-224, also known as secp224r1.
+    P224,
+    /// NIST P-256, also known as secp256r1 or prime256v1.
+    P256,
+    /// NIST P-384, also known as secp384r1.
+    P384,
+    /// NIST P-521, also known as secp521r1.
+    P521,
+}
+
+impl Curve {
+    /// All supported curves, ordered by size.
+    pub const ALL: [Curve; 8] = [
+        Curve::Secp112r1,
+        Curve::Secp128r1,
+        Curve::Secp160r1,
+        Curve::P192,
+        Curve::P224,
+        Curve::P256,
+        Curve::P384,
+        Curve::P521,
+    ];
+
+    /// Get the common name of the curve.
+    pub fn name(&self) -> &'static str {
+        match *self {
+            Curve::Secp112r1 => "secp112r1",
+            Curve::Secp128r1 => "secp128r1",
+            Curve::Secp160r1 => "secp160r1",
+            Curve::P192 => "P-192",
+            Curve::P224 => "P-224",
+            Curve::P256 => "P-256",
+            Curve::P384 => "P-384",
+            Curve::P521 => "P-521",
+        }
+    }
+
+    /// Get the object identifier of the curve in dotted notation.
+    pub fn oid(&self) -> &'static str {
+        match *self {
+            Curve::Secp112r1 => "1.3.132.0.6",
+            Curve::Secp128r1 => "1.3.132.0.28",
+            Curve::Secp160r1 => "1.3.132.0.8",
+            Curve::P192 => "1.2.840.10045.3.1.1",
+            Curve::P224 => "1.3.132.0.33",
+            Curve::P256 => "1.2.840.10045.3.1.7",
+            Curve::P384 => "1.3.132.0.34",
+            Curve::P521 => "1.3.132.0.35",
+        }
+    }
+
+    /// Find a curve by its object identifier in dotted notation.
+    pub fn from_oid(oid: &str) -> Option<Self> {
+        Curve::ALL.iter().cloned().find(|curve| curve.oid() == oid)
+    }
+
+    /// Get the size of the curve in bytes.
+    ///
+    /// This is the size of a private key or of one coordinate of a public point.
+    pub fn size(&self) -> usize {
+        match *self {
+            Curve::Secp112r1 => 14,
+            Curve::Secp128r1 => 16,
+            Curve::Secp160r1 => 20,
+            Curve::P192 => 24,
+            Curve::P224 => 28,
+            Curve::P256 => 32,
+            Curve::P384 => 48,
+            Curve::P521 => 66,
+        }
+    }
+
+    /// Get the size of the curve in bits.
+    pub fn bits(&self) -> usize {
+        match *self {
+            Curve::P521 => 521,
+            _ => self.size() * 8,
+        }
+    }
+
+    fn from_size(size: usize) -> Option<Self> {
+        Curve::ALL.iter().cloned().find(|curve| curve.size() == size)
+    }
+
+    /// Get the LibTomCrypt parameters of this curve.
+    fn params(&self) -> &'static ffi::ltc_ecc_set_type {
+        ::init();
+
+        unsafe {
+            let sets = ptr::addr_of!(ffi::ltc_ecc_sets) as *const ffi::ltc_ecc_set_type;
+            (0..)
+                .map(|i| &*sets.offset(i))
+                .take_while(|set| set.size != 0)
+                .find(|set| set.size as usize == self.size())
+                .expect("curve not available")
+        }
+    }
+}
+
 impl EccKey {
     /// Create a new ecc key pair.
     ///
@@ -23,6 +141,7 @@ impl EccKey {
     /// | 32      | 256   |
     /// | 48      | 384   |
     /// | 65      | 521   |
+    #[deprecated(note = "use `EccKey::generate` with a `Curve` instead")]
     pub fn new(prng: rand::Algorithm, keysize: c_uint) -> Result<Self> {
         unsafe {
             let mut k = mem::uninitialized();
@@ -36,6 +155,32 @@ impl EccKey {
         }
     }
 
+    /// Generate a new key pair on the given curve.
+    pub fn generate(curve: Curve, prng: &mut Prng) -> Result<Self> {
+        unsafe {
+            let mut k = mem::zeroed();
+            tryt!(ffi::ecc_make_key_ex(
+                prng.raw(),
+                prng.algorithm().index(),
+                &mut k,
+                curve.params(),
+            ));
+            Ok(EccKey(k))
+        }
+    }
+
+    /// Get the curve of this key.
+    pub fn curve(&self) -> Curve {
+        unsafe {
+            Curve::from_size((*self.0.dp).size as usize).expect("key uses an unknown curve")
+        }
+    }
+
+    /// Get the size of this key in bytes, see [`Curve::size`](enum.Curve.html#method.size).
+    pub fn size(&self) -> usize {
+        unsafe { ffi::ecc_get_size(&self.0 as *const _ as *mut _) as usize }
+    }
+
     /// Import a private or public ecc key from the tomcrypt format.
     pub fn import(data: &[u8]) -> Result<Self> {
         unsafe {
@@ -81,11 +226,141 @@ impl EccKey {
         }
     }
 
+    /// Import a public key from an uncompressed ANSI X9.63 point (`0x04 || x || y`).
+    ///
+    /// This is the format used by most other libraries and protocols for raw public keys. The point is checked to lie
+    /// on `curve`.
+    pub fn import_x963(data: &[u8], curve: Curve) -> Result<Self> {
+        if data.len() != 1 + 2 * curve.size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_PACKET));
+        }
+
+        let key = unsafe {
+            let mut res = mem::zeroed();
+            tryt!(ffi::ecc_ansi_x963_import_ex(
+                data.as_ptr(),
+                data.len() as c_ulong,
+                &mut res,
+                curve.params() as *const _ as *mut _,
+            ));
+            EccKey(res)
+        };
+
+        unsafe {
+            let dp = &*key.0.dp;
+            if !internal::ecc_is_point_on_curve(dp, key.0.pubkey.x, key.0.pubkey.y)? {
+                return Err(Error::from_code(ffi::CRYPT_INVALID_PACKET));
+            }
+        }
+
+        Ok(key)
+    }
+
+    /// Export the public key as an uncompressed ANSI X9.63 point (`0x04 || x || y`).
+    pub fn export_x963(&self) -> Result<Vec<u8>> {
+        unsafe {
+            let mut buf = vec![0; ffi::ECC_BUF_SIZE as usize];
+            let mut len = buf.len() as c_ulong;
+            tryt!(ffi::ecc_ansi_x963_export(
+                &self.0 as *const _ as *mut _,
+                buf.as_mut_ptr(),
+                &mut len,
+            ));
+            buf.truncate(len as usize);
+            Ok(buf)
+        }
+    }
+
     /// If this key stores a private key.
     pub fn is_private(&self) -> bool {
         self.0.type_ == ffi::PK_PRIVATE as c_int
     }
 
+    /// Sign a message digest with ECDSA.
+    ///
+    /// The signature is returned in the DER encoded format used by LibTomCrypt, a sequence of the two integers `r`
+    /// and `s`. If the digest is longer than the curve order, it is truncated.
+    pub fn sign_hash(&self, digest: &[u8], prng: &mut Prng) -> Result<Vec<u8>> {
+        unsafe {
+            let mut buf = vec![0; ffi::ECC_BUF_SIZE as usize];
+            let mut len = buf.len() as c_ulong;
+            tryt!(ffi::ecc_sign_hash(
+                digest.as_ptr(),
+                digest.len() as c_ulong,
+                buf.as_mut_ptr(),
+                &mut len,
+                prng.raw(),
+                prng.algorithm().index(),
+                &self.0 as *const _ as *mut _,
+            ));
+            buf.truncate(len as usize);
+            Ok(buf)
+        }
+    }
+
+    /// Verify an ECDSA signature of a message digest.
+    ///
+    /// Returns `false` if the signature does not match and an error if the signature cannot be decoded.
+    pub fn verify_hash(&self, signature: &[u8], digest: &[u8]) -> Result<bool> {
+        unsafe {
+            let mut stat = 0;
+            tryt!(ffi::ecc_verify_hash(
+                signature.as_ptr(),
+                signature.len() as c_ulong,
+                digest.as_ptr(),
+                digest.len() as c_ulong,
+                &mut stat,
+                &self.0 as *const _ as *mut _,
+            ));
+            Ok(stat == 1)
+        }
+    }
+
+    /// Encrypt a short secret to this public key.
+    ///
+    /// A temporary key pair is generated with `prng` and the secret is xored with the `hash` of the shared secret
+    /// between the temporary key and this key. The secret must not be longer than the output of `hash`.
+    ///
+    /// The ciphertext is not authenticated, so it should only carry random keys that are afterwards used with an
+    /// authenticated encryption mode. [`seal`](fn.seal.html) does both for data of any length.
+    pub fn encrypt_key(&self, prng: &mut Prng, hash: Hash, data: &[u8]) -> Result<Vec<u8>> {
+        unsafe {
+            let mut buf = vec![0; ffi::ECC_BUF_SIZE as usize + data.len() + 64];
+            let mut len = buf.len() as c_ulong;
+            tryt!(ffi::ecc_encrypt_key(
+                data.as_ptr(),
+                data.len() as c_ulong,
+                buf.as_mut_ptr(),
+                &mut len,
+                prng.raw(),
+                prng.algorithm().index(),
+                hash.index(),
+                &self.0 as *const _ as *mut _,
+            ));
+            buf.truncate(len as usize);
+            Ok(buf)
+        }
+    }
+
+    /// Decrypt a secret that was encrypted to this key with [`encrypt_key`](#method.encrypt_key).
+    ///
+    /// The hash that was used for encryption is stored in the ciphertext.
+    pub fn decrypt_key(&self, data: &[u8]) -> Result<Vec<u8>> {
+        unsafe {
+            let mut buf = vec![0; data.len()];
+            let mut len = buf.len() as c_ulong;
+            tryt!(ffi::ecc_decrypt_key(
+                data.as_ptr(),
+                data.len() as c_ulong,
+                buf.as_mut_ptr(),
+                &mut len,
+                &self.0 as *const _ as *mut _,
+            ));
+            buf.truncate(len as usize);
+            Ok(buf)
+        }
+    }
+
     /// Derive a shared secret from a private and a public key.
     ///
     /// The length of the generated secret is less or equal to the specified
@@ -110,6 +385,77 @@ impl EccKey {
     }
 }
 
+// The sealed box encrypts the data with a random AES-256 key, so a fixed nonce is never reused.
+const SEAL_KEY_LEN: usize = 32;
+const SEAL_TAG_LEN: usize = 16;
+
+/// Encrypt and authenticate data to a public key.
+///
+/// A random AES-256 key is encrypted to the public key with [`EccKey::encrypt_key`] and SHA-512, and the data is
+/// encrypted with EAX under that key. The sealed box consists of the length of the encrypted key as two big-endian
+/// bytes, the encrypted key, the 16 byte authentication tag and the ciphertext. Use [`open`] with the matching private
+/// key to decrypt it.
+///
+/// [`EccKey::encrypt_key`]: struct.EccKey.html#method.encrypt_key
+/// [`open`]: fn.open.html
+pub fn seal(public_key: &EccKey, data: &[u8]) -> Result<Vec<u8>> {
+    let mut key = [0; SEAL_KEY_LEN];
+    Prng::sprng().read_exact(&mut key).map_err(Error::Io)?;
+
+    let result = seal_with_key(public_key, &key, data);
+    util::zeromem_slice(&mut key);
+    result
+}
+
+fn seal_with_key(public_key: &EccKey, key: &[u8], data: &[u8]) -> Result<Vec<u8>> {
+    let wrapped = public_key.encrypt_key(&mut Prng::sprng(), Hash::sha512(), key)?;
+    if wrapped.len() > u16::MAX as usize {
+        return Err(Error::from_code(ffi::CRYPT_BUFFER_OVERFLOW));
+    }
+
+    // The encrypted key is authenticated as header
+    let (ciphertext, tag) = EaxState::seal(Cipher::aes(), key, &[0; 16], Some(&wrapped), data, SEAL_TAG_LEN)?;
+
+    let mut sealed = Vec::with_capacity(2 + wrapped.len() + SEAL_TAG_LEN + ciphertext.len());
+    sealed.push((wrapped.len() >> 8) as u8);
+    sealed.push(wrapped.len() as u8);
+    sealed.extend_from_slice(&wrapped);
+    sealed.extend_from_slice(&tag);
+    sealed.extend_from_slice(&ciphertext);
+    Ok(sealed)
+}
+
+/// Decrypt and verify data that was encrypted with [`seal`](fn.seal.html).
+///
+/// A wrong private key or a modified sealed box returns [`Error::InvalidTag`].
+///
+/// [`Error::InvalidTag`]: ../enum.Error.html#variant.InvalidTag
+pub fn open(private_key: &EccKey, data: &[u8]) -> Result<Vec<u8>> {
+    if !private_key.is_private() {
+        return Err(Error::from_code(ffi::CRYPT_PK_NOT_PRIVATE));
+    }
+    if data.len() < 2 {
+        return Err(Error::InvalidTag);
+    }
+
+    let wrapped_len = (data[0] as usize) << 8 | data[1] as usize;
+    if data.len() < 2 + wrapped_len + SEAL_TAG_LEN {
+        return Err(Error::InvalidTag);
+    }
+    let (wrapped, rest) = data[2..].split_at(wrapped_len);
+    let (tag, ciphertext) = rest.split_at(SEAL_TAG_LEN);
+
+    // A wrong key or a modified encrypted key either fails to decode or gives a wrong key, report both the same way
+    let mut key = private_key.decrypt_key(wrapped).map_err(|_| Error::InvalidTag)?;
+    if key.len() != SEAL_KEY_LEN {
+        util::zeromem_slice(&mut key);
+        return Err(Error::InvalidTag);
+    }
+    let result = EaxState::open(Cipher::aes(), &key, &[0; 16], Some(wrapped), ciphertext, tag);
+    util::zeromem_slice(&mut key);
+    result
+}
+
 impl Drop for EccKey {
     fn drop(&mut self) {
         unsafe {
@@ -120,14 +466,129 @@ impl Drop for EccKey {
 
 #[cfg(test)]
 mod tests {
+    use hex;
     use super::*;
 
     #[test]
     fn test_shared_secret() {
-        let k1 = EccKey::new(rand::Algorithm::sprng(), 12).unwrap();
-        let k2 = EccKey::new(rand::Algorithm::sprng(), 12).unwrap();
+        let k1 = EccKey::generate(Curve::Secp112r1, &mut Prng::sprng()).unwrap();
+        let k2 = EccKey::generate(Curve::Secp112r1, &mut Prng::sprng()).unwrap();
         let len = 16;
         let secret = EccKey::create_shared_secret(&k1, &k2, len).unwrap();
         assert!(secret.len() <= len);
     }
+
+    #[test]
+    fn test_curve() {
+        for &curve in &Curve::ALL {
+            let key = EccKey::generate(curve, &mut Prng::sprng()).unwrap();
+            assert_eq!(key.curve(), curve);
+            assert_eq!(key.size(), curve.size());
+            assert_eq!(Curve::from_oid(curve.oid()), Some(curve));
+
+            let public = EccKey::import(&key.export_public().unwrap()).unwrap();
+            assert_eq!(public.curve(), curve);
+        }
+        assert_eq!(Curve::P521.bits(), 521);
+        assert_eq!(Curve::from_oid("1.2.3"), None);
+    }
+
+    #[allow(deprecated)]
+    #[test]
+    fn test_new_keysize() {
+        // The keysize selects the smallest curve that is large enough
+        assert_eq!(EccKey::new(rand::Algorithm::sprng(), 12).unwrap().curve(), Curve::Secp112r1);
+        assert_eq!(EccKey::new(rand::Algorithm::sprng(), 65).unwrap().curve(), Curve::P521);
+    }
+
+    #[test]
+    fn test_x963() {
+        for &curve in &Curve::ALL {
+            let key = EccKey::generate(curve, &mut Prng::sprng()).unwrap();
+            let data = key.export_x963().unwrap();
+            assert_eq!(data.len(), 1 + 2 * curve.size());
+            assert_eq!(data[0], 4);
+
+            let public = EccKey::import_x963(&data, curve).unwrap();
+            assert_eq!(public.curve(), curve);
+            assert!(!public.is_private());
+            assert_eq!(public.export_public().unwrap(), key.export_public().unwrap());
+        }
+    }
+
+    #[test]
+    fn test_x963_p256_vector() {
+        // Generator point of P-256
+        let point = hex::decode("046b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296\
+            4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5").unwrap();
+        let key = EccKey::import_x963(&point, Curve::P256).unwrap();
+        assert_eq!(key.export_x963().unwrap(), point);
+
+        // Not on the curve
+        let mut invalid = point.clone();
+        invalid[64] ^= 1;
+        assert!(EccKey::import_x963(&invalid, Curve::P256).is_err());
+
+        // Wrong curve size
+        assert!(EccKey::import_x963(&point, Curve::P384).is_err());
+    }
+
+    #[test]
+    fn test_encrypt_key() {
+        let key = EccKey::generate(Curve::P256, &mut Prng::sprng()).unwrap();
+        let public = EccKey::import(&key.export_public().unwrap()).unwrap();
+        let secret = [5; 32];
+
+        let enc = public.encrypt_key(&mut Prng::sprng(), Hash::sha256(), &secret).unwrap();
+        assert_eq!(key.decrypt_key(&enc).unwrap(), secret);
+        assert!(public.decrypt_key(&enc).is_err());
+
+        // The secret must fit into the hash output
+        assert!(public.encrypt_key(&mut Prng::sprng(), Hash::sha1(), &secret).is_err());
+    }
+
+    #[test]
+    fn test_seal_open() {
+        let key = EccKey::generate(Curve::P256, &mut Prng::sprng()).unwrap();
+        let public = EccKey::import(&key.export_public().unwrap()).unwrap();
+        let secret = [6; 64];
+
+        let sealed = seal(&public, &secret).unwrap();
+        assert_eq!(open(&key, &sealed).unwrap(), &secret[..]);
+
+        let other = EccKey::generate(Curve::P256, &mut Prng::sprng()).unwrap();
+        match open(&other, &sealed) {
+            Err(Error::InvalidTag) => {}
+            r => panic!("Unexpected result {:?}", r),
+        }
+        assert!(open(&public, &sealed).is_err());
+
+        // Any modification is detected, in the encrypted key, the tag or the ciphertext
+        for &i in &[0, 1, 2, 20, sealed.len() - 80, sealed.len() - 1] {
+            let mut tampered = sealed.clone();
+            tampered[i] ^= 1;
+            assert!(open(&key, &tampered).is_err(), "byte {}", i);
+        }
+        assert!(open(&key, &sealed[..sealed.len() - 1]).is_err());
+        assert!(open(&key, &[]).is_err());
+
+        // Data of any length can be sealed
+        let data = vec![7; 1000];
+        assert_eq!(open(&key, &seal(&public, &data).unwrap()).unwrap(), data);
+        assert_eq!(open(&key, &seal(&public, &[]).unwrap()).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_sign_verify() {
+        let key = EccKey::generate(Curve::P256, &mut Prng::sprng()).unwrap();
+        let public = EccKey::import(&key.export_public().unwrap()).unwrap();
+        let digest = [1; 32];
+
+        let sig = key.sign_hash(&digest, &mut Prng::sprng()).unwrap();
+        assert!(public.verify_hash(&sig, &digest).unwrap());
+        assert!(!public.verify_hash(&sig, &[2; 32]).unwrap());
+        assert!(public.verify_hash(&sig[1..], &digest).is_err());
+
+        assert!(public.sign_hash(&digest, &mut Prng::sprng()).is_err());
+    }
 }
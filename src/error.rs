@@ -25,6 +25,18 @@ pub enum Error {
 
     #[fail(display = "{}", _0)]
     Tomcrypt(TomcryptError),
+
+    /// Decrypted data did not contain valid padding.
+    #[fail(display = "Invalid padding.")]
+    InvalidPadding,
+
+    /// The authentication tag of a message did not match, the message was modified or the key or nonce is wrong.
+    #[fail(display = "Invalid authentication tag.")]
+    InvalidTag,
+
+    /// The amount of data did not match the length that was declared in advance.
+    #[fail(display = "Data length {} does not match the declared length {}.", actual, expected)]
+    LengthMismatch { expected: usize, actual: usize },
 }
 
 impl Error {
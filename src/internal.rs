@@ -1,4 +1,8 @@
 //! Internal helper functions used by other modules.
+use error::Result;
+use ffi;
+use std::os::raw::*;
+use std::ptr;
 
 /// Allocate an uninitialized byte vector of a given size.
 pub unsafe fn alloc(size: usize) -> Vec<u8> {
@@ -6,3 +10,84 @@ pub unsafe fn alloc(size: usize) -> Vec<u8> {
     vec.set_len(size);
     vec
 }
+
+/// Write a LibTomCrypt big integer as big-endian bytes.
+pub fn mp_to_bytes(value: *mut c_void) -> Vec<u8> {
+    unsafe {
+        let mut buf = vec![0; (ffi::ltc_mp.unsigned_size.unwrap())(value) as usize];
+        (ffi::ltc_mp.unsigned_write.unwrap())(value, buf.as_mut_ptr());
+        buf
+    }
+}
+
+/// An owned big integer of the LibTomCrypt math provider.
+pub struct Mp(pub *mut c_void);
+
+impl Mp {
+    /// Create a new big integer with the value zero.
+    pub fn new() -> Result<Self> {
+        ::init();
+
+        unsafe {
+            let mut raw = ptr::null_mut();
+            tryt!((ffi::ltc_mp.init.unwrap())(&mut raw));
+            Ok(Mp(raw))
+        }
+    }
+
+    /// Read a big integer from a nul-terminated hexadecimal string.
+    pub unsafe fn from_hex(hex: *const c_char) -> Result<Self> {
+        let res = Self::new()?;
+        tryt!((ffi::ltc_mp.read_radix.unwrap())(res.0, hex, 16));
+        Ok(res)
+    }
+
+    /// Set the value to a small integer.
+    pub fn set_int(&mut self, value: c_ulong) -> Result<()> {
+        unsafe {
+            tryt!((ffi::ltc_mp.set_int.unwrap())(self.0, value as ffi::ltc_mp_digit));
+        }
+        Ok(())
+    }
+}
+
+impl Drop for Mp {
+    fn drop(&mut self) {
+        unsafe {
+            (ffi::ltc_mp.deinit.unwrap())(self.0);
+        }
+    }
+}
+
+/// Check if the given affine point lies on the curve `y^2 = x^3 - 3x + b` over the prime field of `dp`.
+///
+/// All curves supported by LibTomCrypt use `a = -3`.
+pub unsafe fn ecc_is_point_on_curve(dp: &ffi::ltc_ecc_set_type, x: *mut c_void, y: *mut c_void) -> Result<bool> {
+    let mp = ffi::ltc_mp;
+    let prime = Mp::from_hex(dp.prime)?;
+    let b = Mp::from_hex(dp.B)?;
+    let mut three = Mp::new()?;
+    three.set_int(3)?;
+    let lhs = Mp::new()?;
+    let rhs = Mp::new()?;
+    let t = Mp::new()?;
+
+    // Coordinates have to be reduced
+    if (mp.compare.unwrap())(x, prime.0) != ffi::LTC_MP_LT || (mp.compare.unwrap())(y, prime.0) != ffi::LTC_MP_LT {
+        return Ok(false);
+    }
+
+    // lhs = y^2 mod p
+    tryt!((mp.sqrmod.unwrap())(y, prime.0, lhs.0));
+    // rhs = x^3 mod p
+    tryt!((mp.sqrmod.unwrap())(x, prime.0, t.0));
+    tryt!((mp.mulmod.unwrap())(t.0, x, prime.0, rhs.0));
+    // rhs = (rhs + (p - 3x mod p) + b) mod p, which avoids negative numbers
+    tryt!((mp.mulmod.unwrap())(x, three.0, prime.0, t.0));
+    tryt!((mp.sub.unwrap())(prime.0, t.0, t.0));
+    tryt!((mp.add.unwrap())(rhs.0, t.0, rhs.0));
+    tryt!((mp.add.unwrap())(rhs.0, b.0, rhs.0));
+    tryt!((mp.mpdiv.unwrap())(rhs.0, prime.0, ptr::null_mut(), rhs.0));
+
+    Ok((mp.compare.unwrap())(lhs.0, rhs.0) == ffi::LTC_MP_EQ as c_int)
+}
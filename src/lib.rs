@@ -36,6 +36,7 @@ pub mod aead;
 pub mod ecc;
 pub mod hash;
 pub mod mac;
+pub mod pk;
 pub mod rand;
 pub mod symmetric;
 pub mod util;
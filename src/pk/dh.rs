@@ -0,0 +1,347 @@
+//! Finite field Diffie-Hellman key exchange.
+use error::{Error, Result};
+use ffi;
+use internal::mp_to_bytes;
+use rand::Prng;
+use std::mem;
+use std::os::raw::*;
+
+
+/// Diffie-Hellman group parameters, the prime modulus `p` and the generator `g`.
+///
+/// All values are big-endian unsigned integers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DhParams {
+    p: Vec<u8>,
+    g: Vec<u8>,
+}
+
+impl DhParams {
+    /// Select one of the built-in safe-prime groups by its size in bytes.
+    ///
+    /// The smallest group that is at least `size` bytes large is used. The groups are the MODP groups from RFC 3526
+    /// and RFC 7296 with a generator of 2. Larger groups are not available with the TomsFastMath math provider.
+    ///
+    /// | size | #bits |
+    /// |------|-------|
+    /// | 96   | 768   |
+    /// | 128  | 1024  |
+    /// | 192  | 1536  |
+    /// | 256  | 2048  |
+    pub fn from_group_size(size: usize) -> Result<Self> {
+        if size == 0 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_KEYSIZE));
+        }
+
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            let code = ffi::dh_set_pg_groupsize(size as c_int, &mut raw);
+
+            Self::from_raw(raw, code)
+        }
+    }
+
+    /// Create group parameters from the big-endian values of `p` and `g`.
+    ///
+    /// The parameters are not validated, only use groups from a trusted source.
+    pub fn new(p: &[u8], g: &[u8]) -> Result<Self> {
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            let code = ffi::dh_set_pg(p.as_ptr(), p.len() as c_ulong, g.as_ptr(), g.len() as c_ulong, &mut raw);
+
+            Self::from_raw(raw, code)
+        }
+    }
+
+    /// Import group parameters from a DER encoded PKCS #3 `DHParameter`, as written by `openssl dhparam`.
+    ///
+    /// The parameters are not validated, only use groups from a trusted source.
+    pub fn from_dhparam_der(data: &[u8]) -> Result<Self> {
+        if data.is_empty() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_PACKET));
+        }
+
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            let code = ffi::dh_set_pg_dhparam(data.as_ptr(), data.len() as c_ulong, &mut raw);
+
+            Self::from_raw(raw, code)
+        }
+    }
+
+    /// Get the prime modulus `p`.
+    pub fn p(&self) -> &[u8] {
+        &self.p
+    }
+
+    /// Get the generator `g`.
+    pub fn g(&self) -> &[u8] {
+        &self.g
+    }
+
+    /// Read the parameters from a key which was initialized by LibTomCrypt and free it.
+    unsafe fn from_raw(raw: ffi::dh_key, code: c_int) -> Result<Self> {
+        // Freeing an already freed key is a no-op.
+        let key = DhKey(raw);
+        match mem::transmute::<c_int, ffi::Error>(code) {
+            ffi::CRYPT_OK => Ok(key.params()),
+            e => Err(Error::from_code(e)),
+        }
+    }
+
+    /// Create a key that only holds these parameters.
+    fn to_key(&self) -> Result<DhKey> {
+        unsafe {
+            let mut raw = mem::zeroed();
+            tryt!(ffi::dh_set_pg(
+                self.p.as_ptr(),
+                self.p.len() as c_ulong,
+                self.g.as_ptr(),
+                self.g.len() as c_ulong,
+                &mut raw,
+            ));
+
+            Ok(DhKey(raw))
+        }
+    }
+}
+
+
+/// A private or public Diffie-Hellman key.
+///
+/// Public keys are always validated when they are created or imported, so a peer cannot force the shared secret into
+/// a small subgroup by sending a degenerate value like 1 or `p - 1`.
+#[derive(Debug)]
+pub struct DhKey(ffi::dh_key);
+
+impl DhKey {
+    /// Generate a new key pair in the given group.
+    pub fn generate(params: &DhParams, prng: &mut Prng) -> Result<Self> {
+        let key = params.to_key()?;
+
+        unsafe {
+            tryt!(ffi::dh_generate_key(prng.raw(), prng.algorithm().index(), key.raw()));
+        }
+
+        Ok(key)
+    }
+
+    /// Create a public key from group parameters and the big-endian public value `y`, as received from a peer.
+    pub fn from_public(params: &DhParams, y: &[u8]) -> Result<Self> {
+        params.to_key()?.set_key(y, ffi::PK_PUBLIC as c_int)
+    }
+
+    /// Create a private key from group parameters and the big-endian private exponent `x`.
+    ///
+    /// The public value is computed from the private exponent.
+    pub fn from_private(params: &DhParams, x: &[u8]) -> Result<Self> {
+        params.to_key()?.set_key(x, ffi::PK_PRIVATE as c_int)
+    }
+
+    /// Import a private or public key from the LibTomCrypt format.
+    pub fn import(data: &[u8]) -> Result<Self> {
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            tryt!(ffi::dh_import(data.as_ptr(), data.len() as c_ulong, &mut raw));
+
+            Ok(DhKey(raw))
+        }
+    }
+
+    /// Export the public key in the LibTomCrypt format.
+    pub fn export_public(&self) -> Result<Vec<u8>> {
+        self.export(ffi::PK_PUBLIC as c_int)
+    }
+
+    /// Export the private key in the LibTomCrypt format.
+    pub fn export_private(&self) -> Result<Vec<u8>> {
+        if !self.is_private() {
+            return Err(Error::from_code(ffi::CRYPT_PK_NOT_PRIVATE));
+        }
+
+        self.export(ffi::PK_PRIVATE as c_int)
+    }
+
+    /// If this key stores a private key.
+    pub fn is_private(&self) -> bool {
+        self.0.type_ == ffi::PK_PRIVATE as c_int
+    }
+
+    /// Get the group parameters of this key.
+    pub fn params(&self) -> DhParams {
+        DhParams {
+            p: mp_to_bytes(self.0.prime),
+            g: mp_to_bytes(self.0.base),
+        }
+    }
+
+    /// Get the public value `y` as big-endian unsigned integer.
+    ///
+    /// This is the value that is sent to the peer.
+    pub fn y(&self) -> Vec<u8> {
+        mp_to_bytes(self.0.y)
+    }
+
+    /// Get the private exponent `x` as big-endian unsigned integer, if this is a private key.
+    pub fn x(&self) -> Option<Vec<u8>> {
+        if self.is_private() {
+            Some(mp_to_bytes(self.0.x))
+        } else {
+            None
+        }
+    }
+
+    /// Get the size of the group in bytes.
+    pub fn group_size(&self) -> usize {
+        unsafe { ffi::dh_get_groupsize(self.raw()) as usize }
+    }
+
+    /// Derive the shared secret from this private key and the public key of a peer.
+    ///
+    /// Both keys have to use the same group. The public key is validated again before it is used. The secret is
+    /// returned without leading zero bytes, so it is usually passed through a key derivation function.
+    pub fn shared_secret(&self, public_key: &DhKey) -> Result<Vec<u8>> {
+        let mut buf = vec![0; self.group_size()];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::dh_shared_secret(self.raw(), public_key.raw(), buf.as_mut_ptr(), &mut len));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    fn set_key(self, value: &[u8], type_: c_int) -> Result<Self> {
+        unsafe {
+            // `dh_set_key` frees the key on failure, which makes dropping it afterwards a no-op.
+            tryt!(ffi::dh_set_key(value.as_ptr(), value.len() as c_ulong, type_, self.raw()));
+        }
+
+        Ok(self)
+    }
+
+    fn export(&self, type_: c_int) -> Result<Vec<u8>> {
+        // The prime, the generator and one value of at most the group size,
+        // plus some DER overhead.
+        let mut buf = vec![0; self.group_size() * 3 + 64];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::dh_export(buf.as_mut_ptr(), &mut len, type_, self.raw()));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    /// LibTomCrypt takes mutable pointers even for operations that only read
+    /// the key.
+    #[inline]
+    pub(crate) fn raw(&self) -> *mut ffi::dh_key {
+        &self.0 as *const _ as *mut _
+    }
+}
+
+impl Drop for DhKey {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::dh_free(&mut self.0);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hex;
+    use super::*;
+
+    #[test]
+    fn test_dh_groups() {
+        for &(size, p_len) in &[(1, 96), (96, 96), (97, 128), (256, 256)] {
+            let params = DhParams::from_group_size(size).unwrap();
+            assert_eq!(params.p().len(), p_len);
+            assert_eq!(params.g(), [2]);
+        }
+        assert!(DhParams::from_group_size(0).is_err());
+        assert!(DhParams::from_group_size(257).is_err());
+    }
+
+    #[test]
+    fn test_dh_shared_secret() {
+        let params = DhParams::from_group_size(256).unwrap();
+        let k1 = DhKey::generate(&params, &mut Prng::sprng()).unwrap();
+        let k2 = DhKey::generate(&params, &mut Prng::sprng()).unwrap();
+        assert_eq!(k1.group_size(), 256);
+
+        let p1 = DhKey::from_public(&params, &k1.y()).unwrap();
+        let p2 = DhKey::import(&k2.export_public().unwrap()).unwrap();
+        assert!(!p2.is_private());
+        assert_eq!(p2.x(), None);
+
+        let s1 = k1.shared_secret(&p2).unwrap();
+        let s2 = k2.shared_secret(&p1).unwrap();
+        assert_eq!(s1, s2);
+
+        // Private key is required
+        assert!(p1.shared_secret(&p2).is_err());
+
+        // Different groups
+        let other = DhKey::generate(&DhParams::from_group_size(128).unwrap(), &mut Prng::sprng()).unwrap();
+        assert!(other.shared_secret(&p2).is_err());
+    }
+
+    #[test]
+    fn test_dh_vector() {
+        // 1024-bit MODP group with x = 0x11.., peer y = 2^0x22.. mod p
+        let params = DhParams::from_group_size(128).unwrap();
+        let a = DhKey::from_private(&params, &[0x11; 30]).unwrap();
+        let b = DhKey::from_private(&params, &[0x22; 30]).unwrap();
+        let b_public = DhKey::from_public(&params, &b.y()).unwrap();
+
+        let expected = hex::decode("025258b62f2263a52cea2528685f8c46d6da4ade5c7f978b0cc0cfbae620fc826772dfdd0942d26a\
+            31a50eaea990820dc133dd72a1a1fa64eeb281dae7ed0bc02216b18e6753edc9f453e101f825582fea494406830c38bbb2f4715630\
+            775e41add557189f5abff22888f41dd42d0c76ee33d18b8b95024dec7e83853b2d9017").unwrap();
+        assert_eq!(a.shared_secret(&b_public).unwrap(), expected);
+    }
+
+    #[test]
+    fn test_dh_check_pubkey() {
+        let params = DhParams::from_group_size(128).unwrap();
+        let mut p_minus_1 = params.p().to_vec();
+        *p_minus_1.last_mut().unwrap() -= 1;
+
+        assert!(DhKey::from_public(&params, &[0]).is_err());
+        assert!(DhKey::from_public(&params, &[1]).is_err());
+        assert!(DhKey::from_public(&params, &[4]).is_err());
+        assert!(DhKey::from_public(&params, &p_minus_1).is_err());
+        assert!(DhKey::from_public(&params, params.p()).is_err());
+        assert!(DhKey::from_public(&params, &[3]).is_ok());
+    }
+
+    #[test]
+    fn test_dh_export_import() {
+        let params = DhParams::from_dhparam_der(include_bytes!("testdata/dh_param.der")).unwrap();
+        assert_eq!(params.p().len(), 128);
+        assert_eq!(DhParams::new(params.p(), params.g()).unwrap(), params);
+
+        let key = DhKey::generate(&params, &mut Prng::sprng()).unwrap();
+        let private = DhKey::import(&key.export_private().unwrap()).unwrap();
+        assert!(private.is_private());
+        assert_eq!(private.x(), key.x());
+        assert_eq!(private.y(), key.y());
+        assert_eq!(private.params(), params);
+
+        let public = DhKey::import(&key.export_public().unwrap()).unwrap();
+        assert!(public.export_private().is_err());
+        assert!(DhKey::import(&[0x30, 0]).is_err());
+    }
+}
@@ -0,0 +1,529 @@
+//! The Digital Signature Algorithm as defined in FIPS 186-4.
+use error::{Error, Result};
+use ffi;
+use hash::Hash;
+use internal::mp_to_bytes;
+use rand::Prng;
+use std::mem;
+use std::os::raw::*;
+
+
+// The largest modulus in bytes. TomsFastMath is built with numbers of up to 4608 bits, so generating parameters with
+// a 3072 bit modulus never finishes.
+const MAX_DSA_MODULUS_SIZE: usize = 256;
+
+
+/// DSA domain parameters, the primes `p` and `q` and the generator `g`.
+///
+/// A set of parameters can be shared by many keys. All values are big-endian unsigned integers.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DsaParams {
+    p: Vec<u8>,
+    q: Vec<u8>,
+    g: Vec<u8>,
+}
+
+impl DsaParams {
+    /// Generate new domain parameters.
+    ///
+    /// `group_size` is the size of `q` and `modulus_size` the size of `p` in bytes. The FIPS 186-4 combinations
+    /// (20, 128), (28, 256) and (32, 256) are supported, (32, 384) is not available with the TomsFastMath math
+    /// provider. Generating parameters is slow.
+    pub fn generate(prng: &mut Prng, group_size: usize, modulus_size: usize) -> Result<Self> {
+        check_modulus_size(modulus_size)?;
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            let code = ffi::dsa_generate_pqg(
+                prng.raw(),
+                prng.algorithm().index(),
+                group_size as c_int,
+                modulus_size as c_int,
+                &mut raw,
+            );
+
+            Self::from_raw(raw, code)
+        }
+    }
+
+    /// Create domain parameters from the big-endian values of `p`, `q` and `g`.
+    ///
+    /// The parameters are checked for consistency, but `p` and `q` are not tested for primality. Use
+    /// [`DsaKey::verify_key`] for a full validation.
+    ///
+    /// [`DsaKey::verify_key`]: struct.DsaKey.html#method.verify_key
+    pub fn new(p: &[u8], q: &[u8], g: &[u8]) -> Result<Self> {
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            let code = ffi::dsa_set_pqg(
+                p.as_ptr(),
+                p.len() as c_ulong,
+                q.as_ptr(),
+                q.len() as c_ulong,
+                g.as_ptr(),
+                g.len() as c_ulong,
+                &mut raw,
+            );
+
+            Self::from_raw(raw, code)
+        }
+    }
+
+    /// Import domain parameters from a DER encoded `Dss-Parms` sequence, as written by `openssl dsaparam`.
+    ///
+    /// The same checks as in [`new`](#method.new) are applied.
+    pub fn from_dsaparam_der(data: &[u8]) -> Result<Self> {
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            let code = ffi::dsa_set_pqg_dsaparam(data.as_ptr(), data.len() as c_ulong, &mut raw);
+
+            Self::from_raw(raw, code)
+        }
+    }
+
+    /// Get the prime modulus `p`.
+    pub fn p(&self) -> &[u8] {
+        &self.p
+    }
+
+    /// Get the prime `q`, the order of the subgroup.
+    pub fn q(&self) -> &[u8] {
+        &self.q
+    }
+
+    /// Get the generator `g` of the subgroup.
+    pub fn g(&self) -> &[u8] {
+        &self.g
+    }
+
+    /// Read the parameters from a key which was initialized by LibTomCrypt and free it.
+    unsafe fn from_raw(raw: ffi::dsa_key, code: c_int) -> Result<Self> {
+        // Freeing an already freed key is a no-op.
+        let key = DsaKey(raw);
+        match mem::transmute::<c_int, ffi::Error>(code) {
+            ffi::CRYPT_OK => Ok(key.params()),
+            e => Err(Error::from_code(e)),
+        }
+    }
+
+    /// Create a key that only holds these parameters.
+    fn to_key(&self) -> Result<DsaKey> {
+        unsafe {
+            let mut raw = mem::zeroed();
+            tryt!(ffi::dsa_set_pqg(
+                self.p.as_ptr(),
+                self.p.len() as c_ulong,
+                self.q.as_ptr(),
+                self.q.len() as c_ulong,
+                self.g.as_ptr(),
+                self.g.len() as c_ulong,
+                &mut raw,
+            ));
+
+            Ok(DsaKey(raw))
+        }
+    }
+}
+
+
+/// A private or public DSA key.
+#[derive(Debug)]
+pub struct DsaKey(ffi::dsa_key);
+
+impl DsaKey {
+    /// Generate new domain parameters and a key pair.
+    ///
+    /// See [`DsaParams::generate`] for the sizes. To create many keys with the same parameters, generate the
+    /// parameters once and use [`generate`](#method.generate).
+    ///
+    /// [`DsaParams::generate`]: struct.DsaParams.html#method.generate
+    pub fn new(prng: &mut Prng, group_size: usize, modulus_size: usize) -> Result<Self> {
+        check_modulus_size(modulus_size)?;
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            tryt!(ffi::dsa_make_key(
+                prng.raw(),
+                prng.algorithm().index(),
+                group_size as c_int,
+                modulus_size as c_int,
+                &mut raw,
+            ));
+
+            Ok(DsaKey(raw))
+        }
+    }
+
+    /// Generate a new key pair with existing domain parameters.
+    pub fn generate(params: &DsaParams, prng: &mut Prng) -> Result<Self> {
+        let key = params.to_key()?;
+
+        unsafe {
+            tryt!(ffi::dsa_generate_key(prng.raw(), prng.algorithm().index(), key.raw()));
+        }
+
+        Ok(key)
+    }
+
+    /// Create a public key from domain parameters and the big-endian public value `y`.
+    pub fn from_public(params: &DsaParams, y: &[u8]) -> Result<Self> {
+        params.to_key()?.set_key(y, ffi::PK_PUBLIC as c_int)
+    }
+
+    /// Create a private key from domain parameters and the big-endian private value `x`.
+    ///
+    /// The public value is computed from the private value.
+    pub fn from_private(params: &DsaParams, x: &[u8]) -> Result<Self> {
+        params.to_key()?.set_key(x, ffi::PK_PRIVATE as c_int)
+    }
+
+    /// Import a private or public DSA key.
+    ///
+    /// Accepts the LibTomCrypt format, the OpenSSL `DSAPrivateKey` format and X.509 `SubjectPublicKeyInfo`.
+    pub fn import(data: &[u8]) -> Result<Self> {
+        if data.is_empty() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_PACKET));
+        }
+
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            tryt!(ffi::dsa_import(data.as_ptr(), data.len() as c_ulong, &mut raw));
+
+            Ok(DsaKey(raw))
+        }
+    }
+
+    /// Export the public key in the LibTomCrypt format.
+    pub fn export_public(&self) -> Result<Vec<u8>> {
+        self.export(ffi::PK_PUBLIC as c_int)
+    }
+
+    /// Export the public key as DER encoded X.509 `SubjectPublicKeyInfo`.
+    pub fn export_spki(&self) -> Result<Vec<u8>> {
+        self.export(ffi::PK_PUBLIC as c_int | ffi::PK_STD as c_int)
+    }
+
+    /// Export the private key in the LibTomCrypt format.
+    pub fn export_private(&self) -> Result<Vec<u8>> {
+        self.export(ffi::PK_PRIVATE as c_int)
+    }
+
+    /// Export the private key in the DER encoded OpenSSL `DSAPrivateKey` format.
+    pub fn export_private_std(&self) -> Result<Vec<u8>> {
+        self.export(ffi::PK_PRIVATE as c_int | ffi::PK_STD as c_int)
+    }
+
+    /// If this key stores a private key.
+    pub fn is_private(&self) -> bool {
+        self.0.type_ == ffi::PK_PRIVATE as c_int
+    }
+
+    /// Get the domain parameters of this key.
+    pub fn params(&self) -> DsaParams {
+        DsaParams {
+            p: mp_to_bytes(self.0.p),
+            q: mp_to_bytes(self.0.q),
+            g: mp_to_bytes(self.0.g),
+        }
+    }
+
+    /// Get the public value `y` as big-endian unsigned integer.
+    pub fn y(&self) -> Vec<u8> {
+        mp_to_bytes(self.0.y)
+    }
+
+    /// Get the private value `x` as big-endian unsigned integer, if this is a private key.
+    pub fn x(&self) -> Option<Vec<u8>> {
+        if self.is_private() {
+            Some(mp_to_bytes(self.0.x))
+        } else {
+            None
+        }
+    }
+
+    /// Get the size of `q` in bytes.
+    pub fn group_size(&self) -> usize {
+        self.0.qord as usize
+    }
+
+    /// Get the size of `p` in bytes.
+    pub fn modulus_size(&self) -> usize {
+        unsafe { (ffi::ltc_mp.unsigned_size.unwrap())(self.0.p) as usize }
+    }
+
+    /// Validate the key.
+    ///
+    /// This tests the primality of `p` and `q`, the consistency of the domain parameters and the range of the key
+    /// values. For private keys, the public value is checked to match the private value. Returns `false` if the key
+    /// is invalid.
+    pub fn verify_key(&self) -> Result<bool> {
+        let mut stat = 0;
+
+        unsafe {
+            tryt!(ffi::dsa_verify_key(self.raw(), &mut stat));
+        }
+
+        Ok(stat == 1)
+    }
+
+    /// Sign a message digest.
+    ///
+    /// The signature is the DER encoded sequence of the two integers `r` and `s`. If the digest is longer than `q`,
+    /// it is truncated.
+    pub fn sign_hash(&self, digest: &[u8], prng: &mut Prng) -> Result<Vec<u8>> {
+        let mut buf = vec![0; self.group_size() * 2 + 16];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::dsa_sign_hash(
+                digest.as_ptr(),
+                digest.len() as c_ulong,
+                buf.as_mut_ptr(),
+                &mut len,
+                prng.raw(),
+                prng.algorithm().index(),
+                self.raw(),
+            ));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    /// Verify a signature of a message digest.
+    ///
+    /// Returns `false` if the signature does not match and an error if the signature cannot be decoded.
+    pub fn verify_hash(&self, signature: &[u8], digest: &[u8]) -> Result<bool> {
+        let mut stat = 0;
+
+        unsafe {
+            tryt!(ffi::dsa_verify_hash(
+                signature.as_ptr(),
+                signature.len() as c_ulong,
+                digest.as_ptr(),
+                digest.len() as c_ulong,
+                &mut stat,
+                self.raw(),
+            ));
+        }
+
+        Ok(stat == 1)
+    }
+
+    /// Encrypt a short secret to this public key.
+    ///
+    /// A temporary key pair is generated with `prng` and the secret is xored with the `hash` of the shared secret
+    /// between the temporary key and this key. The secret must not be longer than the output of `hash`.
+    ///
+    /// The ciphertext is not authenticated, so it should only carry random keys that are afterwards used with an
+    /// authenticated encryption mode.
+    pub fn encrypt_key(&self, prng: &mut Prng, hash: Hash, data: &[u8]) -> Result<Vec<u8>> {
+        // The hash OID, the temporary public value and the encrypted secret
+        let mut buf = vec![0; self.modulus_size() + data.len() + 64];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::dsa_encrypt_key(
+                data.as_ptr(),
+                data.len() as c_ulong,
+                buf.as_mut_ptr(),
+                &mut len,
+                prng.raw(),
+                prng.algorithm().index(),
+                hash.index(),
+                self.raw(),
+            ));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    /// Decrypt a secret that was encrypted to this key with [`encrypt_key`](#method.encrypt_key).
+    ///
+    /// The hash that was used for encryption is stored in the ciphertext.
+    pub fn decrypt_key(&self, data: &[u8]) -> Result<Vec<u8>> {
+        let mut buf = vec![0; data.len()];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::dsa_decrypt_key(data.as_ptr(), data.len() as c_ulong, buf.as_mut_ptr(), &mut len, self.raw()));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    fn set_key(self, value: &[u8], type_: c_int) -> Result<Self> {
+        unsafe {
+            // `dsa_set_key` frees the key on failure, which makes dropping it afterwards a no-op.
+            tryt!(ffi::dsa_set_key(value.as_ptr(), value.len() as c_ulong, type_, self.raw()));
+        }
+
+        Ok(self)
+    }
+
+    fn export(&self, type_: c_int) -> Result<Vec<u8>> {
+        // The largest format contains p, g and y of the modulus size and q and x
+        // of the group size, plus some DER overhead.
+        let mut buf = vec![0; self.modulus_size() * 3 + self.group_size() * 2 + 64];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::dsa_export(buf.as_mut_ptr(), &mut len, type_, self.raw()));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    /// LibTomCrypt takes mutable pointers even for operations that only read
+    /// the key.
+    #[inline]
+    pub(crate) fn raw(&self) -> *mut ffi::dsa_key {
+        &self.0 as *const _ as *mut _
+    }
+}
+
+impl Drop for DsaKey {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::dsa_free(&mut self.0);
+        }
+    }
+}
+
+fn check_modulus_size(modulus_size: usize) -> Result<()> {
+    if modulus_size > MAX_DSA_MODULUS_SIZE {
+        return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use hash::Hash;
+    use super::*;
+
+    #[test]
+    fn test_dsa_make_key() {
+        let key = DsaKey::new(&mut Prng::sprng(), 20, 128).unwrap();
+        assert!(key.is_private());
+        assert_eq!(key.group_size(), 20);
+        assert_eq!(key.modulus_size(), 128);
+        assert!(key.verify_key().unwrap());
+
+        let public = DsaKey::import(&key.export_public().unwrap()).unwrap();
+        assert!(!public.is_private());
+        assert_eq!(public.y(), key.y());
+        assert_eq!(public.x(), None);
+        assert!(public.verify_key().unwrap());
+
+        let private = DsaKey::import(&key.export_private().unwrap()).unwrap();
+        assert_eq!(private.x(), key.x());
+        assert!(public.export_private().is_err());
+    }
+
+    #[test]
+    fn test_dsa_max_size() {
+        let key = DsaKey::new(&mut Prng::sprng(), 32, 256).unwrap();
+        assert_eq!(key.modulus_size(), 256);
+        assert!(key.verify_key().unwrap());
+
+        let digest = Hash::sha256().hash("hello world").unwrap();
+        let sig = key.sign_hash(&digest, &mut Prng::sprng()).unwrap();
+        assert!(key.verify_hash(&sig, &digest).unwrap());
+
+        assert!(DsaParams::generate(&mut Prng::sprng(), 32, 384).is_err());
+        assert!(DsaKey::new(&mut Prng::sprng(), 32, 384).is_err());
+    }
+
+    #[test]
+    fn test_dsa_params() {
+        let data = include_bytes!("testdata/dsa_param.der");
+        let params = DsaParams::from_dsaparam_der(data).unwrap();
+        assert_eq!(params.q().len(), 20);
+        assert_eq!(DsaParams::new(params.p(), params.q(), params.g()).unwrap(), params);
+
+        // g must generate a subgroup of order q
+        assert!(DsaParams::new(params.p(), params.q(), &[2]).is_err());
+        assert!(DsaParams::from_dsaparam_der(&data[..data.len() - 1]).is_err());
+
+        let k1 = DsaKey::generate(&params, &mut Prng::sprng()).unwrap();
+        let k2 = DsaKey::generate(&params, &mut Prng::sprng()).unwrap();
+        assert_eq!(k1.params(), params);
+        assert_eq!(k2.params(), params);
+        assert_ne!(k1.x(), k2.x());
+        assert!(k1.verify_key().unwrap());
+
+        let private = DsaKey::from_private(&params, &k1.x().unwrap()).unwrap();
+        assert_eq!(private.y(), k1.y());
+        let public = DsaKey::from_public(&params, &k1.y()).unwrap();
+        assert!(!public.is_private());
+        assert!(DsaKey::from_public(&params, &[1]).is_err());
+    }
+
+    #[test]
+    fn test_dsa_import_formats() {
+        let private = DsaKey::import(include_bytes!("testdata/dsa_private.der")).unwrap();
+        let public = DsaKey::import(include_bytes!("testdata/dsa_spki.der")).unwrap();
+        let params = DsaParams::from_dsaparam_der(include_bytes!("testdata/dsa_param.der")).unwrap();
+
+        assert!(private.is_private());
+        assert!(private.verify_key().unwrap());
+        assert_eq!(private.params(), params);
+        assert_eq!(public.y(), private.y());
+
+        assert_eq!(&private.export_private_std().unwrap()[..], &include_bytes!("testdata/dsa_private.der")[..]);
+        assert_eq!(&public.export_spki().unwrap()[..], &include_bytes!("testdata/dsa_spki.der")[..]);
+
+        assert!(DsaKey::import(&[]).is_err());
+        assert!(DsaParams::from_dsaparam_der(&[]).is_err());
+    }
+
+    #[test]
+    fn test_dsa_sign_verify() {
+        let key = DsaKey::import(include_bytes!("testdata/dsa_private.der")).unwrap();
+        let public = DsaKey::import(&key.export_public().unwrap()).unwrap();
+        let digest = Hash::sha1().hash("hello world").unwrap();
+
+        // Signature created by another implementation
+        let sig = include_bytes!("testdata/dsa_sig.der");
+        assert!(public.verify_hash(sig, &digest).unwrap());
+        assert!(!public.verify_hash(sig, &[0; 20]).unwrap());
+        assert!(public.verify_hash(&sig[1..], &digest).is_err());
+
+        let sig = key.sign_hash(&digest, &mut Prng::sprng()).unwrap();
+        assert!(public.verify_hash(&sig, &digest).unwrap());
+        assert!(public.sign_hash(&digest, &mut Prng::sprng()).is_err());
+    }
+
+    #[test]
+    fn test_dsa_encrypt_key() {
+        let key = DsaKey::import(include_bytes!("testdata/dsa_private.der")).unwrap();
+        let public = DsaKey::import(include_bytes!("testdata/dsa_spki.der")).unwrap();
+
+        for &(hash, len) in &[(Hash::sha1(), 20), (Hash::sha256(), 16), (Hash::sha512(), 64)] {
+            let secret = vec![7; len];
+            let enc = public.encrypt_key(&mut Prng::sprng(), hash, &secret).unwrap();
+            assert_eq!(key.decrypt_key(&enc).unwrap(), secret);
+            assert!(public.decrypt_key(&enc).is_err());
+            assert!(key.decrypt_key(&enc[..enc.len() - 1]).is_err());
+        }
+
+        // The secret must fit into the hash output
+        assert!(public.encrypt_key(&mut Prng::sprng(), Hash::sha1(), &[7; 21]).is_err());
+
+        let other = DsaKey::generate(&key.params(), &mut Prng::sprng()).unwrap();
+        let enc = public.encrypt_key(&mut Prng::sprng(), Hash::sha256(), &[8; 32]).unwrap();
+        assert_ne!(other.decrypt_key(&enc).unwrap(), [8; 32]);
+    }
+}
@@ -0,0 +1,5 @@
+//! Public key cryptography.
+pub mod dh;
+pub mod dsa;
+mod pkcs8;
+pub mod rsa;
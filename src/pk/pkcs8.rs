@@ -0,0 +1,171 @@
+//! Decryption of PKCS #8 `EncryptedPrivateKeyInfo` structures.
+//!
+//! LibTomCrypt can only import unencrypted PKCS #8 keys, so password protected keys are decrypted here first. Only
+//! the PBES2 scheme from PKCS #5 v2 is supported, with PBKDF2 and an AES or Triple-DES CBC cipher.
+use error::{Error, Result};
+use ffi;
+use hash::Hash;
+use std::os::raw::*;
+use symmetric::{BlockMode, Cbc, Cipher, Padding};
+use util;
+
+
+const TAG_INTEGER: u8 = 0x02;
+const TAG_OCTET_STRING: u8 = 0x04;
+const TAG_OID: u8 = 0x06;
+const TAG_SEQUENCE: u8 = 0x30;
+
+const OID_PBES2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0d];
+const OID_PBKDF2: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x01, 0x05, 0x0c];
+const OID_HMAC_SHA1: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x07];
+const OID_HMAC_SHA256: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x09];
+const OID_HMAC_SHA512: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x02, 0x0b];
+const OID_DES_EDE3_CBC: &[u8] = &[0x2a, 0x86, 0x48, 0x86, 0xf7, 0x0d, 0x03, 0x07];
+const OID_AES128_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x02];
+const OID_AES192_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x16];
+const OID_AES256_CBC: &[u8] = &[0x60, 0x86, 0x48, 0x01, 0x65, 0x03, 0x04, 0x01, 0x2a];
+
+
+/// A minimal DER reader, just enough to walk the PBES2 parameters.
+struct Der<'a>(&'a [u8]);
+
+impl<'a> Der<'a> {
+    /// Read the next element, which must have the given tag, and return its contents.
+    fn read(&mut self, tag: u8) -> Result<&'a [u8]> {
+        if self.0.len() < 2 || self.0[0] != tag {
+            return Err(invalid_packet());
+        }
+
+        let (len, header) = match self.0[1] {
+            len if len < 0x80 => (len as usize, 2),
+            0x81..=0x84 => {
+                let n = (self.0[1] & 0x7f) as usize;
+                if self.0.len() < 2 + n {
+                    return Err(invalid_packet());
+                }
+                let len = self.0[2..2 + n].iter().fold(0, |len, &b| (len << 8) | b as usize);
+                (len, 2 + n)
+            }
+            _ => return Err(invalid_packet()),
+        };
+
+        if self.0.len() - header < len {
+            return Err(invalid_packet());
+        }
+        let content = &self.0[header..header + len];
+        self.0 = &self.0[header + len..];
+        Ok(content)
+    }
+
+    /// Read a small non-negative integer.
+    fn read_int(&mut self) -> Result<u32> {
+        let content = self.read(TAG_INTEGER)?;
+        if content.is_empty() || content.len() > 5 || content[0] & 0x80 != 0
+            || (content.len() == 5 && content[0] != 0) {
+            return Err(invalid_packet());
+        }
+        Ok(content.iter().fold(0, |n, &b| (n << 8) | b as u32))
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.0.first().cloned()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+}
+
+fn invalid_packet() -> Error {
+    Error::from_code(ffi::CRYPT_INVALID_PACKET)
+}
+
+fn unsupported() -> Error {
+    Error::from_code(ffi::CRYPT_PK_INVALID_TYPE)
+}
+
+
+/// Decrypt a DER encoded `EncryptedPrivateKeyInfo` and return the DER encoded `PrivateKeyInfo`.
+///
+/// A wrong password is usually reported as [`Error::InvalidPadding`].
+///
+/// [`Error::InvalidPadding`]: ../../enum.Error.html#variant.InvalidPadding
+pub(crate) fn decrypt(data: &[u8], password: &[u8]) -> Result<Vec<u8>> {
+    // EncryptedPrivateKeyInfo ::= SEQUENCE {
+    //     encryptionAlgorithm AlgorithmIdentifier,
+    //     encryptedData OCTET STRING }
+    let mut info = Der(Der(data).read(TAG_SEQUENCE)?);
+    let mut algorithm = Der(info.read(TAG_SEQUENCE)?);
+    let encrypted = info.read(TAG_OCTET_STRING)?;
+
+    if algorithm.read(TAG_OID)? != OID_PBES2 {
+        return Err(unsupported());
+    }
+
+    // PBES2-params ::= SEQUENCE {
+    //     keyDerivationFunc AlgorithmIdentifier,
+    //     encryptionScheme AlgorithmIdentifier }
+    let mut params = Der(algorithm.read(TAG_SEQUENCE)?);
+    let mut kdf = Der(params.read(TAG_SEQUENCE)?);
+    let mut scheme = Der(params.read(TAG_SEQUENCE)?);
+
+    let (cipher, key_len) = match scheme.read(TAG_OID)? {
+        OID_AES128_CBC => (Cipher::aes(), 16),
+        OID_AES192_CBC => (Cipher::aes(), 24),
+        OID_AES256_CBC => (Cipher::aes(), 32),
+        OID_DES_EDE3_CBC => (Cipher::find("3des").ok_or_else(unsupported)?, 24),
+        _ => return Err(unsupported()),
+    };
+    let iv = scheme.read(TAG_OCTET_STRING)?;
+
+    if kdf.read(TAG_OID)? != OID_PBKDF2 {
+        return Err(unsupported());
+    }
+
+    // PBKDF2-params ::= SEQUENCE {
+    //     salt OCTET STRING,
+    //     iterationCount INTEGER,
+    //     keyLength INTEGER OPTIONAL,
+    //     prf AlgorithmIdentifier DEFAULT algid-hmacWithSHA1 }
+    let mut kdf_params = Der(kdf.read(TAG_SEQUENCE)?);
+    let salt = kdf_params.read(TAG_OCTET_STRING)?;
+    let iterations = kdf_params.read_int()?;
+    if kdf_params.peek() == Some(TAG_INTEGER) && kdf_params.read_int()? as usize != key_len {
+        return Err(invalid_packet());
+    }
+    let hash = if kdf_params.is_empty() {
+        Hash::sha1()
+    } else {
+        let mut prf = Der(kdf_params.read(TAG_SEQUENCE)?);
+        match prf.read(TAG_OID)? {
+            OID_HMAC_SHA1 => Hash::sha1(),
+            OID_HMAC_SHA256 => Hash::sha256(),
+            OID_HMAC_SHA512 => Hash::sha512(),
+            _ => return Err(unsupported()),
+        }
+    };
+
+    if iterations == 0 || iterations > c_int::MAX as u32 {
+        return Err(invalid_packet());
+    }
+
+    let mut key = vec![0; key_len];
+    let mut len = key_len as c_ulong;
+    unsafe {
+        tryt!(ffi::pkcs_5_alg2(
+            password.as_ptr(),
+            password.len() as c_ulong,
+            salt.as_ptr(),
+            salt.len() as c_ulong,
+            iterations as c_int,
+            hash.index(),
+            key.as_mut_ptr(),
+            &mut len,
+        ));
+    }
+
+    let result = Cbc::new(cipher, iv, &key, None).and_then(|mut cbc| cbc.decrypt_padded(encrypted, Padding::Pkcs7));
+    util::zeromem_slice(&mut key);
+
+    result
+}
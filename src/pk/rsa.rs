@@ -0,0 +1,767 @@
+//! RSA public key cryptography as defined in PKCS #1.
+use error::{Error, Result};
+use ffi;
+use hash::Hash;
+use internal::mp_to_bytes;
+use pk::pkcs8;
+use rand::Prng;
+use std::mem;
+use std::os::raw::*;
+use std::ptr;
+use util;
+
+
+// Values of `enum ltc_pkcs_1_paddings`, which is not part of the generated bindings.
+const LTC_PKCS_1_V1_5: c_int = 1;
+const LTC_PKCS_1_OAEP: c_int = 2;
+const LTC_PKCS_1_PSS: c_int = 3;
+
+// The modulus size limits in bits, as `MIN_RSA_SIZE` and `MAX_RSA_SIZE` in newer LibTomCrypt versions. TomsFastMath
+// is built with numbers of up to 4608 bits, so the products of larger moduli overflow and their keys can't be used.
+const MIN_RSA_SIZE: usize = 1024;
+const MAX_RSA_SIZE: usize = 2048;
+
+
+/// The salt length of a PSS signature.
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum PssSaltLength {
+    /// The largest salt that fits for the key and hash, see [`RsaKey::max_pss_salt_len`].
+    ///
+    /// [`RsaKey::max_pss_salt_len`]: struct.RsaKey.html#method.max_pss_salt_len
+    Max,
+    /// A salt of the given length in bytes.
+    Len(usize),
+}
+
+
+/// The private components of an RSA key, as big-endian unsigned integers.
+///
+/// Used to construct a private key with [`RsaKey::from_components`]. All values are required, LibTomCrypt uses the
+/// Chinese Remainder Theorem for all private key operations.
+///
+/// [`RsaKey::from_components`]: struct.RsaKey.html#method.from_components
+#[derive(Clone, Copy, Debug)]
+pub struct RsaPrivateComponents<'a> {
+    /// The private exponent.
+    pub d: &'a [u8],
+    /// The first prime factor of the modulus.
+    pub p: &'a [u8],
+    /// The second prime factor of the modulus.
+    pub q: &'a [u8],
+    /// `d mod (p - 1)`
+    pub dp: &'a [u8],
+    /// `d mod (q - 1)`
+    pub dq: &'a [u8],
+    /// `q^-1 mod p`
+    pub qp: &'a [u8],
+}
+
+
+/// A private or public RSA key.
+#[derive(Debug)]
+pub struct RsaKey(ffi::rsa_key);
+
+impl RsaKey {
+    /// Generate a new RSA key pair.
+    ///
+    /// `bits` is the size of the modulus in bits, between 1024 and 2048 and a multiple of 8. Larger keys are not
+    /// available with the TomsFastMath math provider. `exponent` is the public exponent, it must be odd and at least 3.
+    /// 65537 is a good choice.
+    pub fn new(prng: &mut Prng, bits: usize, exponent: u32) -> Result<Self> {
+        if !(MIN_RSA_SIZE..=MAX_RSA_SIZE).contains(&bits) {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_KEYSIZE));
+        }
+        if bits % 8 != 0 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            tryt!(ffi::rsa_make_key(
+                prng.raw(),
+                prng.algorithm().index(),
+                (bits / 8) as c_int,
+                exponent as c_long,
+                &mut raw,
+            ));
+
+            Ok(RsaKey(raw))
+        }
+    }
+
+    /// Import a private or public RSA key.
+    ///
+    /// Accepts a DER encoded PKCS #1 `RSAPublicKey` or `RSAPrivateKey` and X.509 `SubjectPublicKeyInfo`.
+    pub fn import(data: &[u8]) -> Result<Self> {
+        if data.is_empty() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_PACKET));
+        }
+
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            tryt!(ffi::rsa_import(data.as_ptr(), data.len() as c_ulong, &mut raw));
+
+            Ok(RsaKey(raw))
+        }
+    }
+
+    /// Import the public key from a DER encoded X.509 certificate.
+    ///
+    /// The certificate itself is neither parsed any further nor verified.
+    pub fn from_x509_der(data: &[u8]) -> Result<Self> {
+        if data.is_empty() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_PACKET));
+        }
+
+        ::init();
+
+        unsafe {
+            let mut raw: ffi::rsa_key = mem::zeroed();
+            tryt!(ffi::rsa_import_x509(data.as_ptr(), data.len() as c_ulong, &mut raw));
+
+            // LibTomCrypt frees the key and still returns CRYPT_OK if no RSA public key was found.
+            if raw.type_ != ffi::PK_PUBLIC as c_int || raw.N.is_null() {
+                return Err(Error::from_code(ffi::CRYPT_INVALID_PACKET));
+            }
+
+            Ok(RsaKey(raw))
+        }
+    }
+
+    /// Import a private key from a DER encoded PKCS #8 `PrivateKeyInfo`.
+    ///
+    /// If a password is given, the data is expected to be a password protected `EncryptedPrivateKeyInfo`. Only
+    /// PBES2 encryption with PBKDF2 (HMAC-SHA1, HMAC-SHA256 or HMAC-SHA512) and AES-CBC or 3DES-CBC is supported.
+    /// A wrong password is usually reported as [`Error::InvalidPadding`].
+    ///
+    /// [`Error::InvalidPadding`]: ../../enum.Error.html#variant.InvalidPadding
+    pub fn from_pkcs8_der(data: &[u8], password: Option<&[u8]>) -> Result<Self> {
+        ::init();
+
+        let mut decrypted = match password {
+            Some(password) => Some(pkcs8::decrypt(data, password)?),
+            None => None,
+        };
+
+        let res = {
+            let data = decrypted.as_deref().unwrap_or(data);
+
+            unsafe {
+                let mut raw = mem::zeroed();
+                let code = ffi::rsa_import_pkcs8(data.as_ptr(), data.len() as c_ulong, ptr::null(), 0, &mut raw);
+
+                match mem::transmute::<c_int, ffi::Error>(code) {
+                    ffi::CRYPT_OK => Ok(RsaKey(raw)),
+                    e => Err(Error::from_code(e)),
+                }
+            }
+        };
+
+        if let Some(ref mut decrypted) = decrypted {
+            util::zeromem_slice(decrypted);
+        }
+        res
+    }
+
+    /// Construct a key from its raw components, given as big-endian unsigned integers.
+    ///
+    /// `n` is the modulus and `e` the public exponent. If `private` is `None`, a public key is created.
+    pub fn from_components(n: &[u8], e: &[u8], private: Option<RsaPrivateComponents>) -> Result<Self> {
+        ::init();
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            let (d, d_len) = match private {
+                Some(ref private) => (private.d.as_ptr(), private.d.len() as c_ulong),
+                None => (ptr::null(), 0),
+            };
+
+            // On failure, the rsa_set_* functions free the key themselves.
+            tryt!(ffi::rsa_set_key(
+                n.as_ptr(),
+                n.len() as c_ulong,
+                e.as_ptr(),
+                e.len() as c_ulong,
+                d,
+                d_len,
+                &mut raw,
+            ));
+
+            if let Some(private) = private {
+                if raw.type_ != ffi::PK_PRIVATE as c_int {
+                    // An empty private exponent was given
+                    ffi::rsa_free(&mut raw);
+                    return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+                }
+
+                tryt!(ffi::rsa_set_factors(
+                    private.p.as_ptr(),
+                    private.p.len() as c_ulong,
+                    private.q.as_ptr(),
+                    private.q.len() as c_ulong,
+                    &mut raw,
+                ));
+                tryt!(ffi::rsa_set_crt_params(
+                    private.dp.as_ptr(),
+                    private.dp.len() as c_ulong,
+                    private.dq.as_ptr(),
+                    private.dq.len() as c_ulong,
+                    private.qp.as_ptr(),
+                    private.qp.len() as c_ulong,
+                    &mut raw,
+                ));
+            }
+
+            Ok(RsaKey(raw))
+        }
+    }
+
+    /// Export the public key as a DER encoded PKCS #1 `RSAPublicKey`.
+    pub fn export_public(&self) -> Result<Vec<u8>> {
+        self.export(ffi::PK_PUBLIC as c_int)
+    }
+
+    /// Export the public key as a DER encoded X.509 `SubjectPublicKeyInfo`.
+    ///
+    /// This is the format used by OpenSSL for public key files.
+    pub fn export_spki(&self) -> Result<Vec<u8>> {
+        self.export(ffi::PK_PUBLIC as c_int | ffi::PK_STD as c_int)
+    }
+
+    /// Export the private key as a DER encoded PKCS #1 `RSAPrivateKey`.
+    ///
+    /// Fails if this is only a public key.
+    pub fn export_private(&self) -> Result<Vec<u8>> {
+        self.export(ffi::PK_PRIVATE as c_int)
+    }
+
+    /// Encrypt a short message with OAEP padding as defined in PKCS #1 v2.1.
+    ///
+    /// `hash` is used for OAEP and for the MGF1 mask generation function. The optional `label` is bound to the
+    /// ciphertext and must be passed again on decryption. The message must be at most `size() - 2 * hash.size() - 2`
+    /// bytes long.
+    pub fn encrypt_oaep(&self, prng: &mut Prng, hash: Hash, label: Option<&[u8]>, plaintext: &[u8])
+        -> Result<Vec<u8>> {
+        self.encrypt_ex(prng, hash.index(), LTC_PKCS_1_OAEP, label, plaintext)
+    }
+
+    /// Decrypt a message that was encrypted with OAEP padding.
+    ///
+    /// `hash` and `label` have to match the values used for encryption. Returns [`Error::InvalidPadding`] if the
+    /// decrypted message is not padded correctly, which is also the case if the wrong key, hash or label is used.
+    ///
+    /// [`Error::InvalidPadding`]: ../../enum.Error.html#variant.InvalidPadding
+    pub fn decrypt_oaep(&self, hash: Hash, label: Option<&[u8]>, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.decrypt_ex(hash.index(), LTC_PKCS_1_OAEP, label, ciphertext)
+    }
+
+    /// Encrypt a short message with PKCS #1 v1.5 padding.
+    ///
+    /// **Legacy:** this padding scheme is only provided for compatibility with old peers. Use
+    /// [`encrypt_oaep`](#method.encrypt_oaep) instead whenever possible.
+    ///
+    /// The message must be at most `size() - 11` bytes long.
+    pub fn encrypt_pkcs1v15(&self, prng: &mut Prng, plaintext: &[u8]) -> Result<Vec<u8>> {
+        self.encrypt_ex(prng, -1, LTC_PKCS_1_V1_5, None, plaintext)
+    }
+
+    /// Decrypt a message that was encrypted with PKCS #1 v1.5 padding.
+    ///
+    /// **Legacy:** this padding scheme is only provided for compatibility with old peers. Use
+    /// [`decrypt_oaep`](#method.decrypt_oaep) instead whenever possible. Revealing whether the padding of a
+    /// ciphertext is valid, e.g. through error messages or timing, allows an attacker to decrypt messages.
+    ///
+    /// Returns [`Error::InvalidPadding`] if the decrypted message is not padded correctly.
+    ///
+    /// [`Error::InvalidPadding`]: ../../enum.Error.html#variant.InvalidPadding
+    pub fn decrypt_pkcs1v15(&self, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        self.decrypt_ex(-1, LTC_PKCS_1_V1_5, None, ciphertext)
+    }
+
+    /// Sign a message digest with PKCS #1 v1.5 padding.
+    ///
+    /// **Legacy:** this padding scheme is only provided for compatibility with old peers. Prefer PSS signatures
+    /// for new protocols.
+    ///
+    /// `digest` is the output of `hash`, the object identifier of `hash` is encoded in the signature as a
+    /// `DigestInfo` structure.
+    pub fn sign_pkcs1v15(&self, hash: Hash, digest: &[u8]) -> Result<Vec<u8>> {
+        self.sign_ex(None, hash.index(), LTC_PKCS_1_V1_5, 0, digest)
+    }
+
+    /// Verify a PKCS #1 v1.5 signature of a message digest.
+    ///
+    /// **Legacy:** this padding scheme is only provided for compatibility with old peers. Prefer PSS signatures
+    /// for new protocols.
+    ///
+    /// Returns `false` if the signature does not match the digest and [`Error::InvalidPadding`] if the signature is
+    /// not padded correctly.
+    ///
+    /// [`Error::InvalidPadding`]: ../../enum.Error.html#variant.InvalidPadding
+    pub fn verify_pkcs1v15(&self, hash: Hash, signature: &[u8], digest: &[u8]) -> Result<bool> {
+        self.verify_ex(hash.index(), LTC_PKCS_1_V1_5, 0, signature, digest)
+    }
+
+    /// Sign a message digest with PSS padding as defined in PKCS #1 v2.1.
+    ///
+    /// `digest` is the output of `hash`, which is also used for the MGF1 mask generation function. The salt is
+    /// generated with `prng`.
+    pub fn sign_pss(&self, prng: &mut Prng, hash: Hash, salt_len: PssSaltLength, digest: &[u8])
+        -> Result<Vec<u8>> {
+        let salt_len = self.pss_salt_len(hash, salt_len);
+        self.sign_ex(Some(prng), hash.index(), LTC_PKCS_1_PSS, salt_len, digest)
+    }
+
+    /// Verify a PSS signature of a message digest.
+    ///
+    /// `hash` and `salt_len` have to match the values used for signing. Returns `false` if the signature does not
+    /// match the digest and [`Error::InvalidPadding`] if the signature is not padded correctly.
+    ///
+    /// [`Error::InvalidPadding`]: ../../enum.Error.html#variant.InvalidPadding
+    pub fn verify_pss(&self, hash: Hash, salt_len: PssSaltLength, signature: &[u8], digest: &[u8])
+        -> Result<bool> {
+        let salt_len = self.pss_salt_len(hash, salt_len);
+        self.verify_ex(hash.index(), LTC_PKCS_1_PSS, salt_len, signature, digest)
+    }
+
+    /// Get the maximum salt length in bytes for PSS signatures with this key and the given hash.
+    pub fn max_pss_salt_len(&self, hash: Hash) -> usize {
+        unsafe { ffi::rsa_sign_saltlen_get_max_ex(LTC_PKCS_1_PSS, hash.index(), self.raw()) as usize }
+    }
+
+    /// If this key stores a private key.
+    pub fn is_private(&self) -> bool {
+        self.0.type_ == ffi::PK_PRIVATE as c_int
+    }
+
+    /// Get the modulus as big-endian unsigned integer.
+    pub fn n(&self) -> Vec<u8> {
+        mp_to_bytes(self.0.N)
+    }
+
+    /// Get the public exponent as big-endian unsigned integer.
+    pub fn e(&self) -> Vec<u8> {
+        mp_to_bytes(self.0.e)
+    }
+
+    /// Get the private exponent as big-endian unsigned integer, if this is a private key.
+    pub fn d(&self) -> Option<Vec<u8>> {
+        self.private_component(self.0.d)
+    }
+
+    /// Get the first prime factor of the modulus as big-endian unsigned integer, if this is a private key.
+    pub fn p(&self) -> Option<Vec<u8>> {
+        self.private_component(self.0.p)
+    }
+
+    /// Get the second prime factor of the modulus as big-endian unsigned integer, if this is a private key.
+    pub fn q(&self) -> Option<Vec<u8>> {
+        self.private_component(self.0.q)
+    }
+
+    /// Get `d mod (p - 1)` as big-endian unsigned integer, if this is a private key.
+    pub fn dp(&self) -> Option<Vec<u8>> {
+        self.private_component(self.0.dP)
+    }
+
+    /// Get `d mod (q - 1)` as big-endian unsigned integer, if this is a private key.
+    pub fn dq(&self) -> Option<Vec<u8>> {
+        self.private_component(self.0.dQ)
+    }
+
+    /// Get `q^-1 mod p` as big-endian unsigned integer, if this is a private key.
+    pub fn qp(&self) -> Option<Vec<u8>> {
+        self.private_component(self.0.qP)
+    }
+
+    /// Get the size of the modulus in bytes.
+    ///
+    /// This is also the length of encrypted messages and signatures produced with this key.
+    pub fn size(&self) -> usize {
+        unsafe { ffi::rsa_get_size(self.raw()) as usize }
+    }
+
+    /// Get the size of the modulus in bits.
+    pub fn bits(&self) -> usize {
+        unsafe { (ffi::ltc_mp.count_bits.unwrap())(self.0.N) as usize }
+    }
+
+    fn export(&self, type_: c_int) -> Result<Vec<u8>> {
+        // A private key contains the modulus, the private exponent and five
+        // values of half the modulus size, plus some DER overhead.
+        let mut buf = vec![0; self.size() * 5 + 64];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::rsa_export(buf.as_mut_ptr(), &mut len, type_, self.raw()));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    fn private_component(&self, value: *mut c_void) -> Option<Vec<u8>> {
+        if self.is_private() {
+            Some(mp_to_bytes(value))
+        } else {
+            None
+        }
+    }
+
+    fn pss_salt_len(&self, hash: Hash, salt_len: PssSaltLength) -> usize {
+        match salt_len {
+            PssSaltLength::Max => self.max_pss_salt_len(hash),
+            PssSaltLength::Len(len) => len,
+        }
+    }
+
+    fn encrypt_ex(&self, prng: &mut Prng, hash: c_int, padding: c_int, label: Option<&[u8]>, plaintext: &[u8])
+        -> Result<Vec<u8>> {
+        let (l, l_len) = label_ptr(label);
+        let mut buf = vec![0; self.size()];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::rsa_encrypt_key_ex(
+                plaintext.as_ptr(),
+                plaintext.len() as c_ulong,
+                buf.as_mut_ptr(),
+                &mut len,
+                l,
+                l_len,
+                prng.raw(),
+                prng.algorithm().index(),
+                hash,
+                padding,
+                self.raw(),
+            ));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    fn decrypt_ex(&self, hash: c_int, padding: c_int, label: Option<&[u8]>, ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let (l, l_len) = label_ptr(label);
+        let mut buf = vec![0; self.size()];
+        let mut len = buf.len() as c_ulong;
+        let mut stat = 0;
+
+        unsafe {
+            let code = ffi::rsa_decrypt_key_ex(
+                ciphertext.as_ptr(),
+                ciphertext.len() as c_ulong,
+                buf.as_mut_ptr(),
+                &mut len,
+                l,
+                l_len,
+                hash,
+                padding,
+                &mut stat,
+                self.raw(),
+            );
+
+            // A wrongly sized ciphertext is reported as an invalid packet as
+            // well, but that is not a padding error.
+            match mem::transmute::<c_int, ffi::Error>(code) {
+                ffi::CRYPT_OK | ffi::CRYPT_INVALID_PACKET if stat == 0 && ciphertext.len() == self.size() => {
+                    return Err(Error::InvalidPadding);
+                }
+                ffi::CRYPT_OK => (),
+                e => return Err(Error::from_code(e)),
+            }
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    fn sign_ex(&self, prng: Option<&mut Prng>, hash: c_int, padding: c_int, saltlen: usize, digest: &[u8])
+        -> Result<Vec<u8>> {
+        let (p, p_idx) = match prng {
+            Some(prng) => (prng.raw(), prng.algorithm().index()),
+            None => (ptr::null_mut(), -1),
+        };
+        let mut buf = vec![0; self.size()];
+        let mut len = buf.len() as c_ulong;
+
+        unsafe {
+            tryt!(ffi::rsa_sign_hash_ex(
+                digest.as_ptr(),
+                digest.len() as c_ulong,
+                buf.as_mut_ptr(),
+                &mut len,
+                padding,
+                p,
+                p_idx,
+                hash,
+                saltlen as c_ulong,
+                self.raw(),
+            ));
+        }
+
+        buf.truncate(len as usize);
+        Ok(buf)
+    }
+
+    fn verify_ex(&self, hash: c_int, padding: c_int, saltlen: usize, signature: &[u8], digest: &[u8])
+        -> Result<bool> {
+        let mut stat = 0;
+
+        unsafe {
+            let code = ffi::rsa_verify_hash_ex(
+                signature.as_ptr(),
+                signature.len() as c_ulong,
+                digest.as_ptr(),
+                digest.len() as c_ulong,
+                padding,
+                hash,
+                saltlen as c_ulong,
+                &mut stat,
+                self.raw(),
+            );
+
+            match mem::transmute::<c_int, ffi::Error>(code) {
+                ffi::CRYPT_OK => Ok(stat == 1),
+                ffi::CRYPT_INVALID_PACKET if signature.len() == self.size() => Err(Error::InvalidPadding),
+                e => Err(Error::from_code(e)),
+            }
+        }
+    }
+
+    /// LibTomCrypt takes mutable pointers even for operations that only read
+    /// the key.
+    #[inline]
+    pub(crate) fn raw(&self) -> *mut ffi::rsa_key {
+        &self.0 as *const _ as *mut _
+    }
+}
+
+fn label_ptr(label: Option<&[u8]>) -> (*const c_uchar, c_ulong) {
+    match label {
+        Some(label) => (label.as_ptr(), label.len() as c_ulong),
+        None => (ptr::null(), 0),
+    }
+}
+
+impl Drop for RsaKey {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::rsa_free(&mut self.0);
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+
+    #[test]
+    fn test_rsa_make_key() {
+        let key = RsaKey::new(&mut Prng::sprng(), 1024, 65537).unwrap();
+
+        assert!(key.is_private());
+        assert_eq!(key.size(), 128);
+        assert_eq!(key.bits(), 1024);
+
+        for &bits in &[0, 8, 1016, 2056, 3072, 4096] {
+            assert!(RsaKey::new(&mut Prng::sprng(), bits, 65537).is_err());
+        }
+        assert!(RsaKey::new(&mut Prng::sprng(), 1028, 65537).is_err());
+    }
+
+    #[test]
+    fn test_rsa_max_size() {
+        let key = RsaKey::new(&mut Prng::sprng(), 2048, 65537).unwrap();
+        let public = RsaKey::import(&key.export_public().unwrap()).unwrap();
+        assert_eq!(key.bits(), 2048);
+
+        let digest = Hash::sha256().hash("hello world").unwrap();
+        let sig = key.sign_pss(&mut Prng::sprng(), Hash::sha256(), PssSaltLength::Max, &digest).unwrap();
+        assert!(public.verify_pss(Hash::sha256(), PssSaltLength::Max, &sig, &digest).unwrap());
+        let sig = key.sign_pkcs1v15(Hash::sha256(), &digest).unwrap();
+        assert!(public.verify_pkcs1v15(Hash::sha256(), &sig, &digest).unwrap());
+
+        let enc = public.encrypt_oaep(&mut Prng::sprng(), Hash::sha256(), None, &[4; 32]).unwrap();
+        assert_eq!(key.decrypt_oaep(Hash::sha256(), None, &enc).unwrap(), [4; 32]);
+        let enc = public.encrypt_pkcs1v15(&mut Prng::sprng(), &[5; 32]).unwrap();
+        assert_eq!(key.decrypt_pkcs1v15(&enc).unwrap(), [5; 32]);
+    }
+
+    #[test]
+    fn test_rsa_export_import() {
+        let key = RsaKey::new(&mut Prng::sprng(), 1024, 65537).unwrap();
+
+        let private = key.export_private().unwrap();
+        let imported = RsaKey::import(&private).unwrap();
+        assert!(imported.is_private());
+        assert_eq!(imported.export_private().unwrap(), private);
+
+        let public = key.export_public().unwrap();
+        let imported = RsaKey::import(&public).unwrap();
+        assert!(!imported.is_private());
+        assert_eq!(imported.size(), key.size());
+        assert_eq!(imported.export_public().unwrap(), public);
+        assert!(imported.export_private().is_err());
+
+        assert!(RsaKey::import(&[]).is_err());
+    }
+
+    #[test]
+    fn test_rsa_import_formats() {
+        let pkcs1 = include_bytes!("testdata/rsa_pkcs1.der");
+        let spki = include_bytes!("testdata/rsa_spki.der");
+
+        let key = RsaKey::from_pkcs8_der(include_bytes!("testdata/rsa_pkcs8.der"), None).unwrap();
+        assert!(key.is_private());
+        assert_eq!(key.export_private().unwrap(), &pkcs1[..]);
+        assert_eq!(key.export_spki().unwrap(), &spki[..]);
+
+        let public = RsaKey::from_x509_der(include_bytes!("testdata/rsa_cert.der")).unwrap();
+        assert!(!public.is_private());
+        assert_eq!(public.export_spki().unwrap(), &spki[..]);
+        assert_eq!(public.export_public().unwrap(), key.export_public().unwrap());
+
+        let public = RsaKey::import(spki).unwrap();
+        assert_eq!(public.export_public().unwrap(), key.export_public().unwrap());
+
+        for data in &[&[][..], &[0x30, 0x00], &[0x30, 0x03, 0x02, 0x01, 0x05]] {
+            assert!(RsaKey::from_x509_der(data).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rsa_import_encrypted_pkcs8() {
+        let pkcs1 = include_bytes!("testdata/rsa_pkcs1.der");
+        let aes = include_bytes!("testdata/rsa_pkcs8_aes.der");
+        let des = include_bytes!("testdata/rsa_pkcs8_3des.der");
+
+        for data in &[&aes[..], &des[..]] {
+            let key = RsaKey::from_pkcs8_der(data, Some(b"tomcrypt")).unwrap();
+            assert_eq!(key.export_private().unwrap(), &pkcs1[..]);
+
+            assert!(RsaKey::from_pkcs8_der(data, Some(b"wrong")).is_err());
+            assert!(RsaKey::from_pkcs8_der(data, None).is_err());
+        }
+    }
+
+    #[test]
+    fn test_rsa_components() {
+        let key = RsaKey::new(&mut Prng::sprng(), 1024, 65537).unwrap();
+        assert_eq!(key.e(), [1, 0, 1]);
+        assert_eq!(key.n().len(), 128);
+
+        let d = key.d().unwrap();
+        let p = key.p().unwrap();
+        let q = key.q().unwrap();
+        let dp = key.dp().unwrap();
+        let dq = key.dq().unwrap();
+        let qp = key.qp().unwrap();
+        let private = RsaPrivateComponents { d: &d, p: &p, q: &q, dp: &dp, dq: &dq, qp: &qp };
+
+        let imported = RsaKey::from_components(&key.n(), &key.e(), Some(private)).unwrap();
+        assert!(imported.is_private());
+        assert_eq!(imported.export_private().unwrap(), key.export_private().unwrap());
+
+        let public = RsaKey::from_components(&key.n(), &key.e(), None).unwrap();
+        assert!(!public.is_private());
+        assert_eq!(public.export_public().unwrap(), key.export_public().unwrap());
+        assert_eq!(public.n(), key.n());
+        assert_eq!(public.d(), None);
+
+        // Keys built from components work like generated keys
+        let digest = Hash::sha256().hash("hello world").unwrap();
+        let sig = imported.sign_pss(&mut Prng::sprng(), Hash::sha256(), PssSaltLength::Max, &digest).unwrap();
+        assert!(public.verify_pss(Hash::sha256(), PssSaltLength::Max, &sig, &digest).unwrap());
+
+        let private = RsaPrivateComponents { d: &[], p: &p, q: &q, dp: &dp, dq: &dq, qp: &qp };
+        assert!(RsaKey::from_components(&key.n(), &key.e(), Some(private)).is_err());
+    }
+
+    #[test]
+    fn test_rsa_oaep() {
+        let key = RsaKey::new(&mut Prng::sprng(), 1024, 65537).unwrap();
+        let public = RsaKey::import(&key.export_public().unwrap()).unwrap();
+        let data = [4; 32];
+        let label = b"label";
+
+        let enc = public.encrypt_oaep(&mut Prng::sprng(), Hash::sha256(), Some(label), &data).unwrap();
+        assert_eq!(enc.len(), key.size());
+
+        let dec = key.decrypt_oaep(Hash::sha256(), Some(label), &enc).unwrap();
+        assert_eq!(dec, data);
+
+        match key.decrypt_oaep(Hash::sha256(), None, &enc) {
+            Err(Error::InvalidPadding) => (),
+            res => panic!("unexpected result {:?}", res),
+        }
+        match key.decrypt_oaep(Hash::sha1(), Some(label), &enc) {
+            Err(Error::InvalidPadding) => (),
+            res => panic!("unexpected result {:?}", res),
+        }
+        assert!(public.decrypt_oaep(Hash::sha256(), Some(label), &enc).is_err());
+    }
+
+    #[test]
+    fn test_rsa_pkcs1v15_encrypt() {
+        let key = RsaKey::new(&mut Prng::sprng(), 1024, 65537).unwrap();
+        let data = [4; 32];
+
+        let enc = key.encrypt_pkcs1v15(&mut Prng::sprng(), &data).unwrap();
+        assert_eq!(key.decrypt_pkcs1v15(&enc).unwrap(), data);
+
+        // Encrypted with OAEP, so the v1.5 padding is invalid
+        let enc = key.encrypt_oaep(&mut Prng::sprng(), Hash::sha1(), None, &data).unwrap();
+        match key.decrypt_pkcs1v15(&enc) {
+            Err(Error::InvalidPadding) => (),
+            res => panic!("unexpected result {:?}", res),
+        }
+    }
+
+    #[test]
+    fn test_rsa_pkcs1v15_sign() {
+        let key = RsaKey::new(&mut Prng::sprng(), 1024, 65537).unwrap();
+        let digest = Hash::sha256().hash("hello world").unwrap();
+
+        let sig = key.sign_pkcs1v15(Hash::sha256(), &digest).unwrap();
+        assert_eq!(sig.len(), key.size());
+        assert!(key.verify_pkcs1v15(Hash::sha256(), &sig, &digest).unwrap());
+
+        let other = Hash::sha256().hash("hello moon").unwrap();
+        assert!(!key.verify_pkcs1v15(Hash::sha256(), &sig, &other).unwrap());
+        assert!(!key.verify_pkcs1v15(Hash::sha512(), &sig, &digest).unwrap());
+
+        // v1.5 signatures are deterministic
+        assert_eq!(key.sign_pkcs1v15(Hash::sha256(), &digest).unwrap(), sig);
+    }
+
+    #[test]
+    fn test_rsa_pss() {
+        let key = RsaKey::new(&mut Prng::sprng(), 1024, 65537).unwrap();
+        let public = RsaKey::import(&key.export_public().unwrap()).unwrap();
+        let digest = Hash::sha256().hash("hello world").unwrap();
+        let other = Hash::sha256().hash("hello moon").unwrap();
+
+        assert_eq!(key.max_pss_salt_len(Hash::sha256()), 128 - 32 - 2);
+
+        for &salt_len in &[PssSaltLength::Len(0), PssSaltLength::Len(16), PssSaltLength::Max] {
+            let sig = key.sign_pss(&mut Prng::sprng(), Hash::sha256(), salt_len, &digest).unwrap();
+            assert_eq!(sig.len(), key.size());
+            assert!(public.verify_pss(Hash::sha256(), salt_len, &sig, &digest).unwrap());
+            assert!(!public.verify_pss(Hash::sha256(), salt_len, &sig, &other).unwrap());
+        }
+
+        // A v1.5 signature does not carry PSS padding
+        let sig = key.sign_pkcs1v15(Hash::sha256(), &digest).unwrap();
+        match public.verify_pss(Hash::sha256(), PssSaltLength::Max, &sig, &digest) {
+            Err(Error::InvalidPadding) => (),
+            res => panic!("unexpected result {:?}", res),
+        }
+
+        assert!(public.sign_pss(&mut Prng::sprng(), Hash::sha256(), PssSaltLength::Max, &digest).is_err());
+    }
+}
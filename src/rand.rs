@@ -169,6 +169,11 @@ impl Prng {
 
         Ok(())
     }
+
+    #[inline]
+    pub(crate) fn raw(&mut self) -> *mut ffi::prng_state {
+        &mut self.raw
+    }
 }
 
 impl io::Read for Prng {
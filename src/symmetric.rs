@@ -10,6 +10,7 @@ use std::ffi::{CStr, CString};
 use std::mem;
 use std::os::raw::*;
 use std::slice;
+use util;
 
 
 /// A symmetric encryption cipher.
@@ -139,6 +140,187 @@ pub trait CipherMode {
 }
 
 
+/// A cipher mode with an initialization vector that can be replaced on an existing state.
+///
+/// Setting a new IV restarts the mode as if the state had been created with that IV, but keeps the key schedule, so
+/// one state can encrypt many messages without running the key setup again.
+pub trait IvMode {
+    /// Get the IV of the next block.
+    ///
+    /// Before any data is processed, this is the IV passed to `new` or [`set_iv`]. Afterwards it is the IV that
+    /// continues the stream at the next block, so a state with the same key and `set_iv(&iv())` encrypts the rest of
+    /// the message exactly like this state. In CBC and CFB mode it is the last ciphertext block, in CTR mode the next
+    /// counter, in OFB mode the last keystream block and in LRW mode the index of the next block.
+    ///
+    /// A stream that stopped in the middle of a block can't be continued with an IV.
+    ///
+    /// [`set_iv`]: #tymethod.set_iv
+    fn iv(&self) -> Vec<u8>;
+
+    /// Replace the IV and restart the mode. The IV has to be one block long.
+    fn set_iv(&mut self, iv: &[u8]) -> Result<()>;
+}
+
+
+/// A padding scheme to extend messages to a multiple of the block size of a [`BlockMode`].
+///
+/// All schemes except `Zero` always add at least one byte, so a whole block is added to messages which already are a
+/// multiple of the block size.
+///
+/// [`BlockMode`]: trait.BlockMode.html
+#[derive(Clone, Copy, Debug, Eq, PartialEq)]
+pub enum Padding {
+    /// PKCS #7 padding, every padding byte is the number of padding bytes.
+    Pkcs7,
+    /// ISO/IEC 7816-4 padding, a `0x80` byte followed by zero bytes.
+    Iso7816,
+    /// ANSI X.923 padding, zero bytes followed by the number of padding bytes.
+    AnsiX923,
+    /// Zero bytes up to the next block boundary, if any.
+    ///
+    /// Unpadding removes all trailing zero bytes of the last block, so this scheme is only suitable for data which
+    /// doesn't end with zero bytes.
+    Zero,
+}
+
+impl Padding {
+    /// Return the data extended with the padding to a multiple of the block size.
+    ///
+    /// The block size has to be between 1 and 255 bytes.
+    pub fn pad(&self, data: &[u8], block_size: usize) -> Result<Vec<u8>> {
+        if !(1..=255).contains(&block_size) {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let pad = match *self {
+            Padding::Zero => (block_size - data.len() % block_size) % block_size,
+            _ => block_size - data.len() % block_size,
+        };
+
+        let mut padded = Vec::with_capacity(data.len() + pad);
+        padded.extend_from_slice(data);
+        match *self {
+            Padding::Pkcs7 => padded.resize(data.len() + pad, pad as u8),
+            Padding::Iso7816 => {
+                padded.push(0x80);
+                padded.resize(data.len() + pad, 0);
+            }
+            Padding::AnsiX923 => {
+                padded.resize(data.len() + pad - 1, 0);
+                padded.push(pad as u8);
+            }
+            Padding::Zero => padded.resize(data.len() + pad, 0),
+        }
+
+        Ok(padded)
+    }
+
+    /// Return the data without the padding.
+    ///
+    /// The padding is checked in constant time, any invalid padding returns `Error::InvalidPadding`.
+    pub fn unpad<'a>(&self, data: &'a [u8], block_size: usize) -> Result<&'a [u8]> {
+        if data.is_empty() || !(1..=255).contains(&block_size) || data.len() % block_size != 0 {
+            return if *self == Padding::Zero && data.is_empty() { Ok(data) } else { Err(Error::InvalidPadding) };
+        }
+
+        let last = &data[data.len() - block_size..];
+        let mut bad = 0;
+        let pad = match *self {
+            Padding::Pkcs7 | Padding::AnsiX923 => {
+                let pad = u32::from(last[block_size - 1]);
+                bad |= ct_is_zero(pad) | ct_lt(block_size as u32, pad);
+                for (i, &b) in last.iter().enumerate() {
+                    // The distance from the end of the data, the last byte has a distance of 1.
+                    let distance = (block_size - i) as u32;
+                    let in_pad = !ct_lt(pad, distance);
+                    bad |= in_pad & match *self {
+                        Padding::Pkcs7 => !ct_eq(u32::from(b), pad),
+                        _ => ct_lt(1, distance) & !ct_is_zero(u32::from(b)),
+                    };
+                }
+                pad
+            }
+            Padding::Iso7816 | Padding::Zero => {
+                let (mut pad, mut found) = (0, 0);
+                for (i, &b) in last.iter().enumerate().rev() {
+                    let distance = (block_size - i) as u32;
+                    let is_zero = ct_is_zero(u32::from(b));
+                    if *self == Padding::Iso7816 {
+                        let is_marker = ct_eq(u32::from(b), 0x80);
+                        pad |= !found & is_marker & distance;
+                        bad |= !found & !is_marker & !is_zero;
+                        found |= is_marker | !is_zero;
+                    } else {
+                        found |= !is_zero;
+                        pad = (!found & distance) | (found & pad);
+                    }
+                }
+                if *self == Padding::Iso7816 {
+                    bad |= !found;
+                }
+                pad
+            }
+        };
+
+        if bad != 0 {
+            return Err(Error::InvalidPadding);
+        }
+
+        Ok(&data[..data.len() - pad as usize])
+    }
+}
+
+/// Return all ones if `x` is zero and zero otherwise.
+fn ct_is_zero(x: u32) -> u32 {
+    ((!x & x.wrapping_sub(1)) >> 31).wrapping_neg()
+}
+
+/// Return all ones if `a == b` and zero otherwise.
+fn ct_eq(a: u32, b: u32) -> u32 {
+    ct_is_zero(a ^ b)
+}
+
+/// Return all ones if `a < b` and zero otherwise, both values have to be less than 2^31.
+fn ct_lt(a: u32, b: u32) -> u32 {
+    (a.wrapping_sub(b) >> 31).wrapping_neg()
+}
+
+
+/// A cipher mode which only processes whole blocks and can pad messages.
+pub trait BlockMode: CipherMode {
+    /// The block size of the cipher.
+    fn block_size(&self) -> usize;
+
+    /// Pad the plaintext with the given padding scheme and encrypt it.
+    fn encrypt_padded(&mut self, plaintext: &[u8], padding: Padding) -> Result<Vec<u8>> {
+        let mut buffer = padding.pad(plaintext, self.block_size())?;
+        if let Err(err) = self.encrypt_in_place(&mut buffer) {
+            util::zeromem_slice(&mut buffer);
+            return Err(err);
+        }
+
+        Ok(buffer)
+    }
+
+    /// Decrypt the ciphertext and remove the padding.
+    ///
+    /// Invalid padding returns `Error::InvalidPadding` without revealing which check failed.
+    fn decrypt_padded(&mut self, ciphertext: &[u8], padding: Padding) -> Result<Vec<u8>> {
+        let mut buffer = self.decrypt(ciphertext)?;
+        let len = match padding.unpad(&buffer, self.block_size()) {
+            Ok(data) => data.len(),
+            Err(err) => {
+                util::zeromem_slice(&mut buffer);
+                return Err(err);
+            }
+        };
+        buffer.truncate(len);
+
+        Ok(buffer)
+    }
+}
+
+
 /// ECB or Electronic Codebook Mode is the simplest method to use.
 ///
 /// This mode is very weak since it allows people to swap blocks and perform replay attacks if the same key is used more
@@ -170,6 +352,12 @@ impl CipherMode for Ecb {
     }
 }
 
+impl BlockMode for Ecb {
+    fn block_size(&self) -> usize {
+        self.0.blocklen as usize
+    }
+}
+
 impl Drop for Ecb {
     fn drop(&mut self) {
         unsafe {
@@ -216,6 +404,26 @@ impl CipherMode for Cbc {
     }
 }
 
+impl IvMode for Cbc {
+    fn iv(&self) -> Vec<u8> {
+        self.0.IV[..self.0.blocklen as usize].to_vec()
+    }
+
+    fn set_iv(&mut self, iv: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::cbc_setiv(iv.as_ptr(), iv.len() as c_ulong, &mut self.0));
+        }
+
+        Ok(())
+    }
+}
+
+impl BlockMode for Cbc {
+    fn block_size(&self) -> usize {
+        self.0.blocklen as usize
+    }
+}
+
 impl Drop for Cbc {
     fn drop(&mut self) {
         unsafe {
@@ -225,6 +433,106 @@ impl Drop for Cbc {
 }
 
 
+/// CBC mode with ciphertext stealing in the CS3 variant (NIST SP 800-38A Addendum), as used by Kerberos.
+///
+/// Messages of any length of at least one block are encrypted without padding, so the ciphertext is as long as the
+/// plaintext. The last two ciphertext blocks are always swapped and the last one is truncated to the length of the
+/// partial plaintext block. Every call processes a complete message, use [`set_iv`] to start the next message.
+///
+/// [`set_iv`]: trait.IvMode.html#tymethod.set_iv
+pub struct CbcCs3 {
+    cbc: Cbc,
+    ecb: Ecb,
+}
+
+impl CbcCs3 {
+    pub fn new(cipher: Cipher, iv: &[u8], key: &[u8], rounds: Option<u32>) -> Result<Self> {
+        Ok(CbcCs3 {
+            cbc: Cbc::new(cipher, iv, key, rounds)?,
+            ecb: Ecb::new(cipher, key, rounds)?,
+        })
+    }
+
+    fn block_size(&self) -> usize {
+        self.cbc.0.blocklen as usize
+    }
+}
+
+impl CipherMode for CbcCs3 {
+    unsafe fn encrypt_unchecked(&mut self, plaintext: &[u8], ciphertext: &mut [u8]) -> Result<()> {
+        let bs = self.block_size();
+        if plaintext.len() < bs {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        // Encrypting the zero padded plaintext gives all blocks, only the order and the length differ.
+        let mut buffer = plaintext.to_vec();
+        buffer.resize((plaintext.len() + bs - 1) / bs * bs, 0);
+        if let Err(err) = self.cbc.encrypt_in_place(&mut buffer) {
+            util::zeromem_slice(&mut buffer);
+            return Err(err);
+        }
+
+        let last = buffer.len() - bs;
+        if last == 0 {
+            ciphertext.copy_from_slice(&buffer);
+        } else {
+            let (head, tail) = buffer.split_at(last - bs);
+            let (prev, last) = tail.split_at(bs);
+            ciphertext[..head.len()].copy_from_slice(head);
+            ciphertext[head.len()..head.len() + bs].copy_from_slice(last);
+            let len = ciphertext.len() - head.len() - bs;
+            ciphertext[head.len() + bs..].copy_from_slice(&prev[..len]);
+        }
+
+        Ok(())
+    }
+
+    unsafe fn decrypt_unchecked(&mut self, ciphertext: &[u8], plaintext: &mut [u8]) -> Result<()> {
+        let bs = self.block_size();
+        if ciphertext.len() < bs {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+        if ciphertext.len() == bs {
+            return self.cbc.decrypt_unchecked(ciphertext, plaintext);
+        }
+
+        // The ciphertext ends with the last full block followed by the truncated second to last block.
+        let head = (ciphertext.len() - 1) / bs * bs - bs;
+        let partial = ciphertext.len() - head - bs;
+        let last = &ciphertext[head..head + bs];
+
+        // The stolen bytes of the second to last block are the tail of the decrypted last block, because the last
+        // plaintext block was padded with zeros.
+        let mut decrypted = self.ecb.decrypt(last)?;
+        let mut buffer = Vec::with_capacity(head + 2 * bs);
+        buffer.extend_from_slice(&ciphertext[..head]);
+        buffer.extend_from_slice(&ciphertext[head + bs..]);
+        buffer.extend_from_slice(&decrypted[partial..]);
+        buffer.extend_from_slice(last);
+        util::zeromem_slice(&mut decrypted);
+
+        let result = self.cbc.decrypt_in_place(&mut buffer);
+        if result.is_ok() {
+            plaintext.copy_from_slice(&buffer[..plaintext.len()]);
+        }
+        util::zeromem_slice(&mut buffer);
+
+        result
+    }
+}
+
+impl IvMode for CbcCs3 {
+    fn iv(&self) -> Vec<u8> {
+        self.cbc.iv()
+    }
+
+    fn set_iv(&mut self, iv: &[u8]) -> Result<()> {
+        self.cbc.set_iv(iv)
+    }
+}
+
+
 /// CTR or Counter Mode is a mode which only uses the encryption function of the cipher.
 ///
 /// As long as the initialization vector is random for each message encrypted under the same key replay and swap attacks
@@ -277,6 +585,43 @@ impl CipherMode for Ctr {
     }
 }
 
+impl IvMode for Ctr {
+    fn iv(&self) -> Vec<u8> {
+        let blocklen = self.0.blocklen as usize;
+        let mut ctr = self.0.ctr[..blocklen].to_vec();
+
+        // The counter is only incremented when the next block is started, like in ctr_encrypt.
+        if self.0.padlen == self.0.blocklen {
+            let ctrlen = self.0.ctrlen as usize;
+            let counter = if self.0.mode == ffi::CTR_COUNTER_LITTLE_ENDIAN as c_int {
+                &mut ctr[..ctrlen]
+            } else {
+                &mut ctr[ctrlen..]
+            };
+            let mut bytes: Vec<&mut u8> = counter.iter_mut().collect();
+            if self.0.mode != ffi::CTR_COUNTER_LITTLE_ENDIAN as c_int {
+                bytes.reverse();
+            }
+            for byte in bytes {
+                *byte = byte.wrapping_add(1);
+                if *byte != 0 {
+                    break;
+                }
+            }
+        }
+
+        ctr
+    }
+
+    fn set_iv(&mut self, iv: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::ctr_setiv(iv.as_ptr(), iv.len() as c_ulong, &mut self.0));
+        }
+
+        Ok(())
+    }
+}
+
 impl Drop for Ctr {
     fn drop(&mut self) {
         unsafe {
@@ -287,10 +632,16 @@ impl Drop for Ctr {
 
 
 /// CFB or Ciphertext Feedback Mode is a mode akin to CBC.
-pub struct Cfb(ffi::symmetric_CFB);
+// The IV is kept because LibTomCrypt only stores it encrypted.
+pub struct Cfb(ffi::symmetric_CFB, Vec<u8>);
 
 impl Cfb {
     pub fn new(cipher: Cipher, iv: &[u8], key: &[u8], rounds: Option<u32>) -> Result<Self> {
+        // Validate the IV size since LibTomCrypt doesn't.
+        if iv.len() != cipher.block_size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
         unsafe {
             let mut raw = mem::uninitialized();
             tryt!(ffi::cfb_start(
@@ -302,7 +653,7 @@ impl Cfb {
                 &mut raw,
             ));
 
-            Ok(Cfb(raw))
+            Ok(Cfb(raw, iv.to_vec()))
         }
     }
 }
@@ -321,6 +672,26 @@ impl CipherMode for Cfb {
     }
 }
 
+impl IvMode for Cfb {
+    fn iv(&self) -> Vec<u8> {
+        // The register holds the encrypted IV, after a whole block the last ciphertext block is in the pad.
+        if self.0.padlen == self.0.blocklen {
+            self.0.pad[..self.0.blocklen as usize].to_vec()
+        } else {
+            self.1.clone()
+        }
+    }
+
+    fn set_iv(&mut self, iv: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::cfb_setiv(iv.as_ptr(), iv.len() as c_ulong, &mut self.0));
+        }
+        self.1 = iv.to_vec();
+
+        Ok(())
+    }
+}
+
 impl Drop for Cfb {
     fn drop(&mut self) {
         unsafe {
@@ -331,10 +702,16 @@ impl Drop for Cfb {
 
 
 /// OFB or Output Feedback Mode is a mode akin to CBC as well.
-pub struct Ofb(ffi::symmetric_OFB);
+// The IV is kept because LibTomCrypt only stores it encrypted after `ofb_setiv`.
+pub struct Ofb(ffi::symmetric_OFB, Vec<u8>);
 
 impl Ofb {
     pub fn new(cipher: Cipher, iv: &[u8], key: &[u8], rounds: Option<u32>) -> Result<Self> {
+        // Validate the IV size since LibTomCrypt doesn't.
+        if iv.len() != cipher.block_size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
         unsafe {
             let mut raw = mem::uninitialized();
 
@@ -346,7 +723,7 @@ impl Ofb {
                 &mut raw,
             ));
 
-            Ok(Ofb(raw))
+            Ok(Ofb(raw, iv.to_vec()))
         }
     }
 }
@@ -365,6 +742,26 @@ impl CipherMode for Ofb {
     }
 }
 
+impl IvMode for Ofb {
+    fn iv(&self) -> Vec<u8> {
+        // ofb_setiv already encrypts the IV into the register, after a whole block it holds the last keystream block.
+        if self.0.padlen == 0 {
+            self.1.clone()
+        } else {
+            self.0.IV[..self.0.blocklen as usize].to_vec()
+        }
+    }
+
+    fn set_iv(&mut self, iv: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::ofb_setiv(iv.as_ptr(), iv.len() as c_ulong, &mut self.0));
+        }
+        self.1 = iv.to_vec();
+
+        Ok(())
+    }
+}
+
 impl Drop for Ofb {
     fn drop(&mut self) {
         unsafe {
@@ -374,8 +771,266 @@ impl Drop for Ofb {
 }
 
 
+/// XTS mode (IEEE 1619), a tweakable mode for disk and sector encryption with ciphers with a block size of 16 bytes.
+///
+/// Every sector, or data unit, is encrypted independently with a 16 byte tweak, usually derived from the sector number
+/// with [`sector_tweak`]. A sector has to be at least one block long, the last partial block is handled with
+/// ciphertext stealing, so the ciphertext is as long as the plaintext. XTS does not authenticate the data.
+///
+/// [`sector_tweak`]: #method.sector_tweak
+pub struct Xts(ffi::symmetric_xts);
+
+impl Xts {
+    /// Create a new XTS mode state from the data key `key1` and the tweak key `key2`.
+    ///
+    /// Both keys have the same length and should be generated independently of each other.
+    pub fn new(cipher: Cipher, key1: &[u8], key2: &[u8], rounds: Option<u32>) -> Result<Self> {
+        if key1.len() != key2.len() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_KEYSIZE));
+        }
+
+        unsafe {
+            let mut raw = mem::zeroed();
+            tryt!(ffi::xts_start(
+                cipher.index(),
+                key1.as_ptr(),
+                key2.as_ptr(),
+                key1.len() as c_ulong,
+                rounds.unwrap_or(0) as c_int,
+                &mut raw,
+            ));
+
+            Ok(Xts(raw))
+        }
+    }
+
+    /// Get the tweak for a sector number, encoded as 16 byte little-endian integer as in IEEE 1619.
+    pub fn sector_tweak(sector: u64) -> [u8; 16] {
+        let mut tweak = [0; 16];
+        tweak[..8].copy_from_slice(&sector.to_le_bytes());
+        tweak
+    }
+
+    /// Encrypt a sector with the given tweak and return the ciphertext.
+    pub fn encrypt(&mut self, tweak: &[u8], plaintext: &[u8]) -> Result<Vec<u8>> {
+        let mut ciphertext = plaintext.to_vec();
+        self.encrypt_in_place(tweak, &mut ciphertext)?;
+        Ok(ciphertext)
+    }
+
+    /// Decrypt a sector with the given tweak and return the plaintext.
+    pub fn decrypt(&mut self, tweak: &[u8], ciphertext: &[u8]) -> Result<Vec<u8>> {
+        let mut plaintext = ciphertext.to_vec();
+        self.decrypt_in_place(tweak, &mut plaintext)?;
+        Ok(plaintext)
+    }
+
+    /// Encrypt a sector with the given tweak in place.
+    pub fn encrypt_in_place(&mut self, tweak: &[u8], buffer: &mut [u8]) -> Result<()> {
+        // LibTomCrypt overwrites the tweak, so work on a copy.
+        let mut tweak = Self::check_tweak(tweak)?;
+        unsafe {
+            tryt!(ffi::xts_encrypt(
+                buffer.as_ptr(),
+                buffer.len() as c_ulong,
+                buffer.as_mut_ptr(),
+                tweak.as_mut_ptr(),
+                &mut self.0,
+            ));
+        }
+
+        Ok(())
+    }
+
+    /// Decrypt a sector with the given tweak in place.
+    pub fn decrypt_in_place(&mut self, tweak: &[u8], buffer: &mut [u8]) -> Result<()> {
+        let mut tweak = Self::check_tweak(tweak)?;
+        unsafe {
+            tryt!(ffi::xts_decrypt(
+                buffer.as_ptr(),
+                buffer.len() as c_ulong,
+                buffer.as_mut_ptr(),
+                tweak.as_mut_ptr(),
+                &mut self.0,
+            ));
+        }
+
+        Ok(())
+    }
+
+    fn check_tweak(tweak: &[u8]) -> Result<[u8; 16]> {
+        if tweak.len() != 16 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        let mut res = [0; 16];
+        res.copy_from_slice(tweak);
+        Ok(res)
+    }
+}
+
+impl Drop for Xts {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::xts_done(&mut self.0);
+        }
+    }
+}
+
+
+/// LRW mode, a tweakable mode for disk encryption with ciphers with a block size of 16 bytes.
+///
+/// Every block is encrypted with a tweak derived from the tweak key and the current IV, which is the block index and
+/// incremented after each block. The IV can be read and set between blocks with [`iv`] and [`set_iv`] to process
+/// blocks out of order. Only whole blocks can be processed.
+///
+/// [`iv`]: #method.iv
+/// [`set_iv`]: #method.set_iv
+pub struct Lrw(Box<ffi::symmetric_LRW>);
+
+impl Lrw {
+    /// Create a new LRW mode state from a 16 byte IV, the cipher key and the 16 byte tweak key.
+    pub fn new(cipher: Cipher, iv: &[u8], key: &[u8], tweak: &[u8], rounds: Option<u32>) -> Result<Self> {
+        // LibTomCrypt reads 16 bytes without checking the length.
+        if iv.len() != 16 || tweak.len() != 16 {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        unsafe {
+            // The state contains 64 KiB of multiplication tables, so keep it off the stack.
+            let mut raw: Box<ffi::symmetric_LRW> = Box::new(mem::zeroed());
+            tryt!(ffi::lrw_start(
+                cipher.index(),
+                iv.as_ptr(),
+                key.as_ptr(),
+                key.len() as c_int,
+                tweak.as_ptr(),
+                rounds.unwrap_or(0) as c_int,
+                &mut *raw,
+            ));
+
+            Ok(Lrw(raw))
+        }
+    }
+}
+
+impl CipherMode for Lrw {
+    unsafe fn encrypt_unchecked(&mut self, plaintext: &[u8], ciphertext: &mut [u8]) -> Result<()> {
+        tryt!(ffi::lrw_encrypt(plaintext.as_ptr(), ciphertext.as_mut_ptr(), plaintext.len() as c_ulong, &mut *self.0));
+
+        Ok(())
+    }
+
+    unsafe fn decrypt_unchecked(&mut self, ciphertext: &[u8], plaintext: &mut [u8]) -> Result<()> {
+        tryt!(ffi::lrw_decrypt(ciphertext.as_ptr(), plaintext.as_mut_ptr(), ciphertext.len() as c_ulong, &mut *self.0));
+
+        Ok(())
+    }
+}
+
+impl IvMode for Lrw {
+    /// Get the current IV, which is the index of the next block.
+    fn iv(&self) -> Vec<u8> {
+        self.0.IV.to_vec()
+    }
+
+    /// Set the IV to the index of the next block that is processed.
+    fn set_iv(&mut self, iv: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::lrw_setiv(iv.as_ptr(), iv.len() as c_ulong, &mut *self.0));
+        }
+
+        Ok(())
+    }
+}
+
+impl Drop for Lrw {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::lrw_done(&mut *self.0);
+        }
+    }
+}
+
+
+/// F8 mode, the keystream mode used by 3GPP and the SRTP F8 profile (RFC 3711).
+///
+/// Like [`Ctr`] and [`Ofb`], F8 turns the cipher into a stream cipher, so data of any length can be processed and
+/// encryption and decryption are the same operation. The IV is masked with the key xored with the salt key, which is
+/// extended with `0x55` bytes to the length of the key.
+///
+/// [`Ctr`]: struct.Ctr.html
+/// [`Ofb`]: struct.Ofb.html
+pub struct F8(ffi::symmetric_F8);
+
+impl F8 {
+    /// Create a new F8 mode state, the IV has to be one block long.
+    pub fn new(cipher: Cipher, iv: &[u8], key: &[u8], salt_key: &[u8], rounds: Option<u32>) -> Result<Self> {
+        // LibTomCrypt reads one block of IV without checking the length.
+        if iv.len() != cipher.block_size() {
+            return Err(Error::from_code(ffi::CRYPT_INVALID_ARG));
+        }
+
+        unsafe {
+            let mut raw = mem::zeroed();
+
+            tryt!(ffi::f8_start(
+                cipher.index(),
+                iv.as_ptr(),
+                key.as_ptr(), key.len() as c_int,
+                salt_key.as_ptr(), salt_key.len() as c_int,
+                rounds.unwrap_or(0) as c_int,
+                &mut raw,
+            ));
+
+            Ok(F8(raw))
+        }
+    }
+
+    /// Replace the current keystream block.
+    ///
+    /// This is **not** a per-packet restart: the IV is encrypted with the key instead of the key xored with the salt
+    /// key, and the result is used as the next keystream block. The IV mask and the block counter of the current
+    /// packet are kept. To encrypt a new packet with a new IV, as in RFC 3711, a new state has to be created with
+    /// [`new`]. F8 therefore doesn't implement [`IvMode`].
+    ///
+    /// [`new`]: #method.new
+    /// [`IvMode`]: trait.IvMode.html
+    pub fn set_iv(&mut self, iv: &[u8]) -> Result<()> {
+        unsafe {
+            tryt!(ffi::f8_setiv(iv.as_ptr(), iv.len() as c_ulong, &mut self.0));
+        }
+
+        Ok(())
+    }
+}
+
+impl CipherMode for F8 {
+    unsafe fn encrypt_unchecked(&mut self, plaintext: &[u8], ciphertext: &mut [u8]) -> Result<()> {
+        tryt!(ffi::f8_encrypt(plaintext.as_ptr(), ciphertext.as_mut_ptr(), plaintext.len() as c_ulong, &mut self.0));
+
+        Ok(())
+    }
+
+    unsafe fn decrypt_unchecked(&mut self, ciphertext: &[u8], plaintext: &mut [u8]) -> Result<()> {
+        tryt!(ffi::f8_decrypt(ciphertext.as_ptr(), plaintext.as_mut_ptr(), ciphertext.len() as c_ulong, &mut self.0));
+
+        Ok(())
+    }
+}
+
+impl Drop for F8 {
+    fn drop(&mut self) {
+        unsafe {
+            ffi::f8_done(&mut self.0);
+        }
+    }
+}
+
+
 #[cfg(test)]
 mod tests {
+    use hex;
     use super::*;
 
 
@@ -402,4 +1057,307 @@ mod tests {
 
         assert_eq!(buffer, data);
     }
+
+    #[test]
+    fn test_xts_vectors() {
+        // IEEE 1619 test vectors from the LibTomCrypt self-test
+        let vectors: &[(&str, &str, u64, &str, &str)] = &[
+            ("00000000000000000000000000000000", "00000000000000000000000000000000", 0,
+             "0000000000000000000000000000000000000000000000000000000000000000",
+             "917cf69ebd68b2ec9b9fe9a3eadda692cd43d2f59598ed858c02c2652fbf922e"),
+            ("11111111111111111111111111111111", "22222222222222222222222222222222", 0x3333333333,
+             "4444444444444444444444444444444444444444444444444444444444444444",
+             "c454185e6a16936e39334038acef838bfb186fff7480adc4289382ecd6d394f0"),
+            ("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0", "bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0", 0x123456789a,
+             "4444444444444444444444444444444444444444444444444444444444444444",
+             "b01f86f8edc1863706fa8a4253e34f28af319de38334870f4dd1f94cbe9832f1"),
+            ("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0", "bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0", 0x123456789a,
+             "000102030405060708090a0b0c0d0e0f10",
+             "6c1625db4671522d3d7599601de7ca09ed"),
+            ("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0", "bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0", 0x123456789a,
+             "000102030405060708090a0b0c0d0e0f101112131415161718",
+             "8f4dcbad55558d7b4e01d9379cd4ea22edbf9dace45d6f6a73"),
+            ("fffefdfcfbfaf9f8f7f6f5f4f3f2f1f0", "bfbebdbcbbbab9b8b7b6b5b4b3b2b1b0", 0x123456789a,
+             "000102030405060708090a0b0c0d0e0f101112131415161718191a1b1c1d1e",
+             "d05bc090a8e04f1b3d3ecdd5baec0fd4edbf9dace45d6f6a7306e64be5dd82"),
+        ];
+
+        for &(key1, key2, sector, pt, ct) in vectors {
+            let mut xts = Xts::new(Cipher::aes(), &hex::decode(key1).unwrap(), &hex::decode(key2).unwrap(), None)
+                .unwrap();
+            let tweak = Xts::sector_tweak(sector);
+            let pt = hex::decode(pt).unwrap();
+            let ct = hex::decode(ct).unwrap();
+
+            assert_eq!(xts.encrypt(&tweak, &pt).unwrap(), ct);
+            assert_eq!(xts.decrypt(&tweak, &ct).unwrap(), pt);
+
+            let mut buffer = pt.clone();
+            xts.encrypt_in_place(&tweak, &mut buffer).unwrap();
+            assert_eq!(buffer, ct);
+            xts.decrypt_in_place(&tweak, &mut buffer).unwrap();
+            assert_eq!(buffer, pt);
+        }
+    }
+
+    #[test]
+    fn test_xts_invalid() {
+        let mut xts = Xts::new(Cipher::aes(), &[1; 16], &[2; 16], None).unwrap();
+        let tweak = Xts::sector_tweak(7);
+
+        // A sector has to contain at least one block
+        assert!(xts.encrypt(&tweak, &[0; 15]).is_err());
+        assert!(xts.encrypt(&tweak[..8], &[0; 16]).is_err());
+        // Different sectors produce different ciphertexts
+        assert_ne!(xts.encrypt(&tweak, &[0; 16]).unwrap(), xts.encrypt(&Xts::sector_tweak(8), &[0; 16]).unwrap());
+
+        assert!(Xts::new(Cipher::aes(), &[1; 16], &[2; 32], None).is_err());
+        assert!(Xts::new(Cipher::find("des").unwrap(), &[1; 8], &[2; 8], None).is_err());
+    }
+
+    #[test]
+    fn test_lrw_vectors() {
+        // Test vectors from the LibTomCrypt self-test: key, tweak key, IV, ciphertext of "0123456789ABCDEF"
+        let vectors = [
+            ("4562ac25f828176d4c268414b5680185", "258e2a05e73e9d03ee5a830ccc094c87", "80000000000000000000000000000000",
+             "f1b273cd65a3df5fe95d489254634eb8"),
+            ("59704714f557478cd779e80f54887944", "6753c90cb7d8cde506a047781aad8511", "00000000000000000000000000000002",
+             "00c82bae95bbcde5274f0769b260e136"),
+            ("d82a9134b26a565030fe69e2377f9847", "4eb55d3105973a3f5e23dafb5a45d6c0", "00000000000000000000000200000000",
+             "76322183ed8ff182f9596203690e5e01"),
+        ];
+
+        for &(key, tweak, iv, ct) in &vectors {
+            let iv = hex::decode(iv).unwrap();
+            let mut lrw = Lrw::new(Cipher::aes(), &iv, &hex::decode(key).unwrap(), &hex::decode(tweak).unwrap(), None)
+                .unwrap();
+            assert_eq!(lrw.iv(), iv);
+
+            let enc = lrw.encrypt(b"0123456789ABCDEF").unwrap();
+            assert_eq!(enc, hex::decode(ct).unwrap());
+            // The IV is the index of the next block
+            assert_ne!(lrw.iv(), iv);
+
+            lrw.set_iv(&iv).unwrap();
+            assert_eq!(lrw.decrypt(&enc).unwrap(), b"0123456789ABCDEF");
+        }
+    }
+
+    #[test]
+    fn test_lrw_blocks() {
+        let mut lrw = Lrw::new(Cipher::aes(), &[0; 16], &[1; 16], &[2; 16], None).unwrap();
+        let data: Vec<u8> = (0..48).collect();
+        let enc = lrw.encrypt(&data).unwrap();
+        assert_eq!(lrw.iv(), hex::decode("00000000000000000000000000000003").unwrap());
+
+        // Decrypt the second block on its own
+        lrw.set_iv(&hex::decode("00000000000000000000000000000001").unwrap()).unwrap();
+        assert_eq!(lrw.decrypt(&enc[16..32]).unwrap(), &data[16..32]);
+
+        assert!(lrw.encrypt(&data[..20]).is_err());
+        assert!(lrw.set_iv(&[0; 8]).is_err());
+        assert!(Lrw::new(Cipher::aes(), &[0; 8], &[1; 16], &[2; 16], None).is_err());
+        assert!(Lrw::new(Cipher::aes(), &[0; 16], &[1; 16], &[2; 8], None).is_err());
+    }
+
+    #[test]
+    fn test_f8_vector() {
+        // Test vector from the LibTomCrypt self-test
+        let key = hex::decode("234829008467be186c3de14aae72d62c").unwrap();
+        let salt = hex::decode("32f2870d").unwrap();
+        let iv = hex::decode("006e5cba50681de55c621599d462564a").unwrap();
+        let pt = b"pseudorandomness is the next best thing";
+        let ct = hex::decode("019ce7a26e7854014a6366aa95d4eefd1ad4172a14f9faf455b7f1d4b62bd08f562c0eef7c4802").unwrap();
+
+        let mut f8 = F8::new(Cipher::aes(), &iv, &key, &salt, None).unwrap();
+        assert_eq!(f8.encrypt(&pt[..10]).unwrap(), &ct[..10]);
+        assert_eq!(f8.encrypt(&pt[10..]).unwrap(), &ct[10..]);
+
+        let mut f8 = F8::new(Cipher::aes(), &iv, &key, &salt, None).unwrap();
+        assert_eq!(f8.decrypt(&ct).unwrap(), &pt[..]);
+
+        // Setting the IV doesn't start a new packet
+        let mut f8 = F8::new(Cipher::aes(), &[0; 16], &key, &salt, None).unwrap();
+        f8.set_iv(&iv).unwrap();
+        assert_ne!(f8.encrypt(&pt[..]).unwrap(), ct);
+
+        assert!(f8.set_iv(&iv[..8]).is_err());
+        assert!(F8::new(Cipher::aes(), &iv[..8], &key, &salt, None).is_err());
+    }
+
+    fn check_iv_mode<M: CipherMode + IvMode, F: Fn(&[u8]) -> M>(new: F) {
+        let data: Vec<u8> = (0..64).collect();
+        let (iv1, iv2) = ([1; 16], [2; 16]);
+
+        let mut mode = new(&iv1);
+        assert_eq!(mode.iv(), iv1);
+        let enc1 = mode.encrypt(&data[..32]).unwrap();
+        mode.set_iv(&iv2).unwrap();
+        assert_eq!(mode.iv(), iv2);
+        let enc2 = mode.encrypt(&data[..32]).unwrap();
+        assert_eq!(enc1, new(&iv1).encrypt(&data[..32]).unwrap());
+        assert_eq!(enc2, new(&iv2).encrypt(&data[..32]).unwrap());
+
+        mode.set_iv(&iv1).unwrap();
+        assert_eq!(mode.decrypt(&enc1).unwrap(), &data[..32]);
+
+        // Feeding the IV back restarts the same stream
+        let mut other = new(&iv2);
+        other.set_iv(&new(&iv1).iv()).unwrap();
+        assert_eq!(other.encrypt(&data[..32]).unwrap(), enc1);
+
+        // And after whole blocks it continues the stream
+        let mut mode = new(&iv1);
+        mode.encrypt(&data[..32]).unwrap();
+        let mut other = new(&iv2);
+        other.set_iv(&mode.iv()).unwrap();
+        assert_eq!(other.encrypt(&data[32..]).unwrap(), mode.encrypt(&data[32..]).unwrap());
+
+        assert!(mode.set_iv(&[0; 8]).is_err());
+        assert!(mode.set_iv(&[]).is_err());
+    }
+
+    #[test]
+    fn test_iv_modes() {
+        let key = [7; 16];
+        check_iv_mode(|iv| Cbc::new(Cipher::aes(), iv, &key, None).unwrap());
+        check_iv_mode(|iv| Ctr::new(Cipher::aes(), iv, &key, None, CtrEndianness::BigEndian).unwrap());
+        check_iv_mode(|iv| Ctr::new(Cipher::aes(), iv, &key, None, CtrEndianness::LittleEndian).unwrap());
+        check_iv_mode(|iv| Cfb::new(Cipher::aes(), iv, &key, None).unwrap());
+        check_iv_mode(|iv| Ofb::new(Cipher::aes(), iv, &key, None).unwrap());
+        check_iv_mode(|iv| Lrw::new(Cipher::aes(), iv, &key, &[9; 16], None).unwrap());
+        check_iv_mode(|iv| CbcCs3::new(Cipher::aes(), iv, &key, None).unwrap());
+
+        // The CBC chaining value is the last ciphertext block and continues the message
+        let mut cbc = Cbc::new(Cipher::aes(), &[1; 16], &key, None).unwrap();
+        assert_eq!(cbc.iv(), [1; 16]);
+        let enc = cbc.encrypt(&[3; 48]).unwrap();
+        assert_eq!(cbc.iv(), &enc[32..]);
+        let mut cbc = Cbc::new(Cipher::aes(), &[0; 16], &key, None).unwrap();
+        cbc.set_iv(&enc[..16]).unwrap();
+        assert_eq!(cbc.decrypt(&enc[16..]).unwrap(), &[3; 32][..]);
+
+        // The next CTR counter wraps around
+        let mut ctr = Ctr::new(Cipher::aes(), &[0xff; 16], &key, None, CtrEndianness::BigEndian).unwrap();
+        ctr.encrypt(&[0; 16]).unwrap();
+        assert_eq!(ctr.iv(), [0; 16]);
+        let mut ctr = Ctr::new(Cipher::aes(), &[0xff; 16], &key, None, CtrEndianness::LittleEndian).unwrap();
+        ctr.encrypt(&[0; 16]).unwrap();
+        assert_eq!(ctr.iv(), [0; 16]);
+
+        assert!(Cfb::new(Cipher::aes(), &[0; 8], &key, None).is_err());
+        assert!(Ofb::new(Cipher::aes(), &[0; 8], &key, None).is_err());
+
+        let mut ctr = Ctr::new(Cipher::aes(), &[0; 16], &key, None, CtrEndianness::BigEndian).unwrap();
+        ctr.set_iv(&[5; 16]).unwrap();
+        assert_eq!(ctr.iv(), [5; 16]);
+    }
+
+    #[test]
+    fn test_padding() {
+        let schemes = [
+            (Padding::Pkcs7, "61626303030361626364656667680808080808080808"),
+            (Padding::Iso7816, "61626380000061626364656667688000000000000000"),
+            (Padding::AnsiX923, "61626300000361626364656667680000000000000008"),
+            (Padding::Zero, "616263000000616263646566676800000000000000"),
+        ];
+
+        for &(padding, expected) in &schemes {
+            let expected = hex::decode(expected).unwrap();
+            let (short, long) = expected.split_at(6);
+            assert_eq!(padding.pad(b"abc", 6).unwrap(), short);
+            assert_eq!(padding.unpad(short, 6).unwrap(), b"abc");
+            assert_eq!(padding.pad(b"abcdefgh", 8).unwrap()[..], long[..if padding == Padding::Zero { 8 } else { 16 }]);
+            assert_eq!(padding.unpad(&padding.pad(b"abcdefgh", 8).unwrap(), 8).unwrap(), b"abcdefgh");
+            assert_eq!(padding.unpad(&padding.pad(b"", 8).unwrap(), 8).unwrap(), b"");
+            assert!(padding.unpad(b"abcdefg", 8).is_err());
+
+            // The padding length has to fit into one byte
+            assert!(padding.pad(b"abc", 0).is_err());
+            assert!(padding.pad(b"abc", 256).is_err());
+            assert_eq!(padding.pad(b"abc", 255).unwrap().len(), 255);
+            assert!(padding.unpad(&[0; 256], 256).is_err());
+        }
+
+        let invalid: &[(Padding, &[u8])] = &[
+            (Padding::Pkcs7, &[1, 2, 3, 0]),
+            (Padding::Pkcs7, &[1, 2, 3, 5]),
+            (Padding::Pkcs7, &[1, 2, 3, 2]),
+            (Padding::Pkcs7, &[]),
+            (Padding::Iso7816, &[1, 2, 3, 0]),
+            (Padding::Iso7816, &[1, 0x80, 1, 0]),
+            (Padding::Iso7816, &[0, 0, 0, 0]),
+            (Padding::AnsiX923, &[1, 2, 3, 0]),
+            (Padding::AnsiX923, &[1, 2, 1, 2]),
+            (Padding::AnsiX923, &[0, 0, 0, 5]),
+        ];
+        for &(padding, data) in invalid {
+            match padding.unpad(data, 4) {
+                Err(Error::InvalidPadding) => {}
+                other => panic!("{:?} {:?}: {:?}", padding, data, other.map(|d| d.to_vec())),
+            }
+        }
+        assert_eq!(Padding::Pkcs7.unpad(&[4, 4, 4, 4], 4).unwrap(), b"");
+        assert_eq!(Padding::Iso7816.unpad(&[0x80, 0, 0, 0], 4).unwrap(), b"");
+        assert_eq!(Padding::Iso7816.unpad(&[0x80, 0x80, 0, 0], 4).unwrap(), &[0x80]);
+        assert_eq!(Padding::Zero.unpad(&[1, 0, 2, 0], 4).unwrap(), &[1, 0, 2]);
+    }
+
+    #[test]
+    fn test_block_mode_padding() {
+        let key = [7; 16];
+        let mut ecb = Ecb::new(Cipher::aes(), &key, None).unwrap();
+        let enc = ecb.encrypt_padded(b"hello world", Padding::Pkcs7).unwrap();
+        assert_eq!(enc.len(), 16);
+        assert_eq!(ecb.decrypt(&enc).unwrap(), Padding::Pkcs7.pad(b"hello world", 16).unwrap());
+        assert_eq!(ecb.decrypt_padded(&enc, Padding::Pkcs7).unwrap(), b"hello world");
+        assert!(ecb.decrypt_padded(&enc, Padding::AnsiX923).is_err());
+
+        for &padding in &[Padding::Pkcs7, Padding::Iso7816, Padding::AnsiX923, Padding::Zero] {
+            let data = b"0123456789abcdef0123";
+            let enc = Cbc::new(Cipher::aes(), &[1; 16], &key, None).unwrap().encrypt_padded(data, padding).unwrap();
+            assert_eq!(enc.len(), 32);
+            let mut cbc = Cbc::new(Cipher::aes(), &[1; 16], &key, None).unwrap();
+            assert_eq!(cbc.decrypt_padded(&enc, padding).unwrap(), &data[..], "{:?}", padding);
+            assert!(cbc.decrypt_padded(&enc[..20], padding).is_err());
+        }
+    }
+
+    #[test]
+    fn test_cbc_cs3_vectors() {
+        // Test vectors from RFC 3962, Appendix B
+        let key = hex::decode("636869636b656e207465726979616b69").unwrap();
+        let msg = b"I would like the General Gau's Chicken, please, and wonton soup.";
+        let vectors = [
+            (17, "c6353568f2bf8cb4d8a580362da7ff7f97"),
+            (31, "fc00783e0efdb2c1d445d4c8eff7ed2297687268d6ecccc0c07b25e25ecfe5"),
+            (32, "39312523a78662d5be7fcbcc98ebf5a897687268d6ecccc0c07b25e25ecfe584"),
+            (47, "97687268d6ecccc0c07b25e25ecfe584b3fffd940c16a18c1b5549d2f838029e39312523a78662d5be7fcbcc98ebf5"),
+            (48, "97687268d6ecccc0c07b25e25ecfe5849dad8bbb96c4cdc03bc103e1a194bbd839312523a78662d5be7fcbcc98ebf5a8"),
+            (64, "97687268d6ecccc0c07b25e25ecfe58439312523a78662d5be7fcbcc98ebf5a84807efe836ee89a526730dbc2f7bc840\
+                  9dad8bbb96c4cdc03bc103e1a194bbd8"),
+        ];
+
+        let mut cts = CbcCs3::new(Cipher::aes(), &[0; 16], &key, None).unwrap();
+        for &(len, ct) in &vectors {
+            let ct = hex::decode(ct).unwrap();
+            cts.set_iv(&[0; 16]).unwrap();
+            assert_eq!(cts.encrypt(&msg[..len]).unwrap(), ct);
+            cts.set_iv(&[0; 16]).unwrap();
+            assert_eq!(cts.decrypt(&ct).unwrap(), &msg[..len]);
+
+            let mut buffer = ct.clone();
+            cts.set_iv(&[0; 16]).unwrap();
+            cts.decrypt_in_place(&mut buffer).unwrap();
+            assert_eq!(buffer, &msg[..len]);
+        }
+
+        // A single block is plain CBC
+        let mut cbc = Cbc::new(Cipher::aes(), &[0; 16], &key, None).unwrap();
+        cts.set_iv(&[0; 16]).unwrap();
+        assert_eq!(cts.encrypt(&msg[..16]).unwrap(), cbc.encrypt(&msg[..16]).unwrap());
+
+        assert!(cts.encrypt(&msg[..15]).is_err());
+        assert!(cts.decrypt(&[]).is_err());
+    }
 }